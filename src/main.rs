@@ -6,37 +6,78 @@
 //! 3. For each sample: finding the nearest commit and measuring blob sizes
 //! 4. Outputting CSV and optional PNG plot
 
-use chrono::{DateTime, Duration, NaiveDate, Utc};
-use clap::Parser;
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, SecondsFormat, Utc};
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
 use csv::Writer;
-use git2::Repository;
+use git2::{Repository, RepositoryOpenFlags};
 use indicatif::{ProgressBar, ProgressStyle};
 use plotters::prelude::*;
 use rayon::prelude::*;
+use std::borrow::Cow;
 use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
 use std::error::Error;
+use std::ffi::{OsStr, OsString};
 use std::fmt;
-use std::io::{self, BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, PoisonError};
 
 /// Analyze git repository size over time using commit sampling
 #[derive(Parser, Debug)]
 #[command(name = "git-size-history")]
 #[command(author = "Gautier Portet <gautier@soundconverter.org>", version, about, long_about = None)]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     /// Path to the git repository
     #[arg(default_value = ".")]
     repo_path: PathBuf,
 
     /// Output CSV file path
-    #[arg(short, long)]
-    output: PathBuf,
+    #[arg(short, long, required = true)]
+    output: Option<PathBuf>,
+
+    /// Keep the rows of the existing output CSV and only add sample points newer than its last row
+    #[arg(long, conflicts_with_all = ["breakdown_depth", "by_extension", "categories"])]
+    append: bool,
+
+    /// Reuse the measurements of an interrupted run, saved in the <OUTPUT>.journal checkpoint file
+    #[arg(long, conflicts_with_all = ["breakdown_depth", "by_extension", "categories"])]
+    resume: bool,
 
     /// Generate a plot of cumulative size (PNG format)
     #[arg(long)]
     plot: Option<PathBuf>,
 
+    /// Revision whose history is analyzed (branch, tag, remote-tracking ref or any revision expression)
+    #[arg(long, value_name = "REVSPEC", default_value = "HEAD")]
+    rev: String,
+
+    /// Follow only the first parent of merge commits when selecting sample points (mainline history)
+    #[arg(long)]
+    first_parent: bool,
+
+    /// Commit date used for the commit range, the sampling walk and the dates written in the CSV
+    #[arg(long, value_enum, default_value_t = DateSource::Committer)]
+    date_source: DateSource,
+
+    /// Measure the whole repository: objects reachable from any ref as of each sample date
+    #[arg(long, conflicts_with_all = ["rev", "branches", "tags"])]
+    all: bool,
+
+    /// Measure objects reachable from any local branch as of each sample date
+    #[arg(long, conflicts_with = "rev")]
+    branches: bool,
+
+    /// Measure objects reachable from any tag as of each sample date
+    #[arg(long, conflicts_with = "rev")]
+    tags: bool,
+
     /// Force yearly sampling
     #[arg(long)]
     yearly: bool,
@@ -45,6 +86,74 @@ struct Args {
     #[arg(long)]
     monthly: bool,
 
+    /// Sampling interval: a count and a unit (d, w, mo, q, y), e.g. 1w, 90d, 3mo
+    #[arg(long, conflicts_with_all = ["yearly", "monthly"])]
+    interval: Option<Interval>,
+
+    /// Take N evenly spaced sample points instead of sampling at a fixed interval
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), conflicts_with_all = ["yearly", "monthly", "interval"])]
+    samples: Option<u32>,
+
+    /// Align sample dates on calendar boundaries (first of month, Jan 1, ISO week starts, quarter starts)
+    #[arg(long, conflicts_with = "samples")]
+    calendar: bool,
+
+    /// Sample every Nth commit of the history instead of sampling by date
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), conflicts_with_all = ["yearly", "monthly", "interval", "samples", "calendar"])]
+    every_n_commits: Option<u32>,
+
+    /// Refine sampling where the size changes by more than this between two sample points (e.g. 50MB, 10%)
+    #[arg(long, value_name = "SIZE|PERCENT", conflicts_with_all = ["every_n_commits", "breakdown_depth", "by_extension", "categories"])]
+    refine_threshold: Option<Threshold>,
+
+    /// Shortest interval refinement may split (with --refine-threshold, default: 1d)
+    #[arg(long, value_name = "INTERVAL", requires = "refine_threshold")]
+    min_interval: Option<Interval>,
+
+    /// Maximum total number of sample points after refinement (with --refine-threshold, default: 200)
+    #[arg(long, value_name = "N", requires = "refine_threshold", value_parser = clap::value_parser!(u32).range(2..))]
+    max_samples: Option<u32>,
+
+    /// Place sample points at regular dates, or at each tag of the analyzed history
+    #[arg(long, value_enum, default_value_t = SampleBy::Date, conflicts_with_all = ["yearly", "monthly", "interval", "samples", "calendar", "every_n_commits", "refine_threshold"])]
+    sample_by: SampleBy,
+
+    /// Only sample tags matching this glob (with --sample-by tags), e.g. "v*"
+    #[arg(long, value_name = "GLOB", requires = "sample_by")]
+    tag_pattern: Option<String>,
+
+    /// Order of the CSV rows of tag sample points (with --sample-by tags)
+    #[arg(long, value_enum, requires = "sample_by")]
+    tag_sort: Option<TagSort>,
+
+    /// Start sampling at this date (YYYY-MM-DD, RFC 3339 or relative, e.g. "2.years.ago")
+    #[arg(long, value_name = "DATE")]
+    since: Option<String>,
+
+    /// Stop sampling at this date (YYYY-MM-DD, RFC 3339 or relative, e.g. "6.months.ago")
+    #[arg(long, value_name = "DATE")]
+    until: Option<String>,
+
+    /// Omit the commit and commit-date columns and name the date column "date", as in earlier versions
+    #[arg(long)]
+    no_commit_columns: bool,
+
+    /// Directory of the measurement cache (default: size-history in the git directory)
+    #[arg(long, value_name = "DIR", conflicts_with = "no_cache")]
+    cache_dir: Option<PathBuf>,
+
+    /// Do not read or write the measurement cache
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Number of measurements run in parallel (default: number of CPUs)
+    #[arg(long, short = 'j', value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    jobs: Option<u32>,
+
+    /// Run fewer measurements at once when they would not fit this memory budget (e.g. 8GB), estimated from the repository object count
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_memory: Option<u64>,
+
     /// Enable debug output (show command outputs)
     #[arg(long, short = 'D')]
     debug: bool,
@@ -52,6 +161,72 @@ struct Args {
     /// Also calculate and output uncompressed blob sizes (slower)
     #[arg(long, short = 'U')]
     uncompressed: bool,
+
+    /// Measure the packed size added in each interval (`rev-list --disk-usage <new> ^<old>`)
+    #[arg(long, conflicts_with_all = ["breakdown_depth", "by_extension", "categories"])]
+    added: bool,
+
+    /// Split packed and uncompressed sizes by object type (commits, trees, blobs, tags)
+    #[arg(long, conflicts_with_all = ["breakdown_depth", "by_extension", "categories"])]
+    by_type: bool,
+
+    /// Report the largest blobs introduced between consecutive sample points to this CSV file
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["breakdown_depth", "by_extension", "categories"])]
+    top_blobs: Option<PathBuf>,
+
+    /// Number of blobs reported per interval with --top-blobs
+    #[arg(long, value_name = "N", default_value_t = 10, requires = "top_blobs")]
+    top_n: usize,
+
+    /// Output object counts (objects, commits, trees, blobs, tags)
+    #[arg(long, conflicts_with_all = ["breakdown_depth", "by_extension", "categories"])]
+    counts: bool,
+
+    /// Plot object and blob counts on a secondary y-axis
+    #[arg(long, requires_all = ["counts", "plot"])]
+    plot_counts: bool,
+
+    /// Break sizes down by directory, up to N path components deep (long-format CSV: date,path,packed,uncompressed)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    breakdown_depth: Option<u32>,
+
+    /// Break sizes down by file extension (long-format CSV: date,extension,packed,uncompressed)
+    #[arg(long, conflicts_with = "breakdown_depth")]
+    by_extension: bool,
+
+    /// Break sizes down by category, using a CSV file of `glob,category` lines (first match wins)
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["breakdown_depth", "by_extension"])]
+    categories: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Find the commit where the reachable size jumped by more than a threshold
+    Bisect(BisectArgs),
+}
+
+/// Options of the `bisect` subcommand
+#[derive(ClapArgs, Debug)]
+struct BisectArgs {
+    /// Path to the git repository
+    #[arg(default_value = ".")]
+    repo_path: PathBuf,
+
+    /// Older end of the search: a revision, or a date (YYYY-MM-DD, RFC 3339 or relative)
+    #[arg(long, value_name = "REV|DATE")]
+    from: String,
+
+    /// Newer end of the search: a revision, or a date (YYYY-MM-DD, RFC 3339 or relative)
+    #[arg(long, value_name = "REV|DATE", default_value = "HEAD")]
+    to: String,
+
+    /// Size increase of a single commit to look for (e.g. 50MB, 10%)
+    #[arg(long, value_name = "SIZE|PERCENT")]
+    threshold: Threshold,
+
+    /// Number of new blobs shown for the commit found
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    top_n: usize,
 }
 
 #[derive(Debug)]
@@ -63,6 +238,7 @@ enum GitSizeError {
     Plot(String),
     Command(String),
     Validation(String),
+    Interrupted,
 }
 
 impl fmt::Display for GitSizeError {
@@ -75,6 +251,7 @@ impl fmt::Display for GitSizeError {
             GitSizeError::Plot(e) => write!(f, "Plot error: {}", e),
             GitSizeError::Command(e) => write!(f, "Command error: {}", e),
             GitSizeError::Validation(e) => write!(f, "Validation error: {}", e),
+            GitSizeError::Interrupted => write!(f, "Interrupted"),
         }
     }
 }
@@ -117,6 +294,177 @@ impl From<chrono::OutOfRangeError> for GitSizeError {
 
 type Result<T> = std::result::Result<T, GitSizeError>;
 
+/// The part of the repository history being analyzed
+struct History {
+    /// Commits the history walk starts from: the `--rev` commit, or every selected ref tip
+    tips: Vec<String>,
+    /// Whole-repository mode: each sample measures the union of objects reachable
+    /// from all ref tips as of its date instead of a single commit
+    whole_repository: bool,
+    /// Human-readable description for the summary
+    description: String,
+    /// Only follow the first parent of merge commits when selecting sample points
+    first_parent: bool,
+    /// Commit date used to place commits in time
+    date_source: DateSource,
+    /// Annotated tags among the selected refs (whole-repository mode)
+    annotated_tags: Vec<AnnotatedTag>,
+}
+
+/// An annotated tag object, measured along with the commit it tags
+struct AnnotatedTag {
+    /// Tag object id
+    id: String,
+    /// Author date of the tagged commit
+    author_time: i64,
+    /// Committer date of the tagged commit
+    commit_time: i64,
+}
+
+/// Commit date used to place commits in time
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum DateSource {
+    /// Date the commit was originally authored (kept by rebases and cherry-picks)
+    Author,
+    /// Date the commit was last committed (what `git log` orders by)
+    #[default]
+    Committer,
+}
+
+impl History {
+    /// History of a single revision (`--rev`).
+    fn from_rev(repo: &Repository, rev: &str) -> Result<History> {
+        let commit = resolve_revision(repo, rev)?;
+        Ok(History {
+            tips: vec![commit.id().to_string()],
+            whole_repository: false,
+            description: rev.to_string(),
+            first_parent: false,
+            date_source: DateSource::default(),
+            annotated_tags: Vec::new(),
+        })
+    }
+
+    /// History of every selected ref (`--all`, `--branches`, `--tags`).
+    ///
+    /// `all` selects every ref (including remote-tracking refs and a detached `HEAD`),
+    /// otherwise local branches and/or tags are selected. Refs that do not point
+    /// to a commit (e.g. tags of blobs) are ignored. Annotated tag objects are
+    /// kept apart from the tips, which are commits.
+    fn from_refs(repo: &Repository, all: bool, branches: bool, tags: bool) -> Result<History> {
+        let mut tips = Vec::new();
+        let mut annotated_tags = Vec::new();
+        for reference in repo.references()? {
+            let reference = reference?;
+            let selected =
+                all || (branches && reference.is_branch()) || (tags && reference.is_tag());
+            if !selected {
+                continue;
+            }
+            let Ok(commit) = reference.peel_to_commit() else {
+                continue;
+            };
+            tips.push(commit.id().to_string());
+            if let Some(tag) = reference.target().and_then(|oid| repo.find_tag(oid).ok()) {
+                annotated_tags.push(AnnotatedTag {
+                    id: tag.id().to_string(),
+                    author_time: commit.author().when().seconds(),
+                    commit_time: commit.time().seconds(),
+                });
+            }
+        }
+        if all {
+            if let Ok(commit) = repo.head().and_then(|head| head.peel_to_commit()) {
+                tips.push(commit.id().to_string());
+            }
+        }
+        tips.sort();
+        tips.dedup();
+        annotated_tags.sort_by(|a, b| a.id.cmp(&b.id));
+        annotated_tags.dedup_by(|a, b| a.id == b.id);
+
+        if tips.is_empty() {
+            return Err(GitSizeError::Validation(
+                "No refs pointing to commits found in repository".to_string(),
+            ));
+        }
+
+        let description = if all {
+            "all refs"
+        } else if branches && tags {
+            "all branches and tags"
+        } else if branches {
+            "all branches"
+        } else {
+            "all tags"
+        };
+        Ok(History {
+            description: format!("{} ({} distinct tips)", description, tips.len()),
+            tips,
+            whole_repository: true,
+            first_parent: false,
+            date_source: DateSource::default(),
+            annotated_tags,
+        })
+    }
+
+    /// Date of a commit according to the date source.
+    fn commit_time(&self, commit: &git2::Commit<'_>) -> i64 {
+        match self.date_source {
+            DateSource::Author => commit.author().when().seconds(),
+            DateSource::Committer => commit.time().seconds(),
+        }
+    }
+
+    /// Annotated tags whose commit is dated at or before `time`, measured with
+    /// the ref tips as of that date.
+    fn tags_at(&self, time: i64) -> impl Iterator<Item = String> + '_ {
+        self.annotated_tags
+            .iter()
+            .filter(move |tag| {
+                let tagged = match self.date_source {
+                    DateSource::Author => tag.author_time,
+                    DateSource::Committer => tag.commit_time,
+                };
+                tagged <= time
+            })
+            .map(|tag| tag.id.clone())
+    }
+
+    /// `git rev-list` options printing commits as `<timestamp> <hash>` lines, using
+    /// the date source, followed by the parent hashes with `parents`.
+    ///
+    /// Author dates are walked in `--author-date-order` so that early stops on
+    /// the date stay valid. They need `--format`, which also prints
+    /// `commit <hash>` header lines that callers skip.
+    fn timestamp_args(&self, parents: bool) -> &'static [&'static str] {
+        match (self.date_source, parents) {
+            (DateSource::Committer, false) => &["--timestamp"],
+            (DateSource::Committer, true) => &["--timestamp", "--parents"],
+            (DateSource::Author, false) => &["--author-date-order", "--format=%at %H"],
+            (DateSource::Author, true) => &["--author-date-order", "--format=%at %H %P"],
+        }
+    }
+
+    /// Arguments walking this history with `git rev-list`: walk options, then
+    /// `--stdin` to read the tips from `tips_input()`.
+    fn rev_list_args(&self) -> &'static [&'static str] {
+        if self.first_parent {
+            &["--first-parent", "--stdin"]
+        } else {
+            &["--stdin"]
+        }
+    }
+
+    /// Standard input of the commands run with `rev_list_args()`: the tips.
+    fn tips_input(&self) -> Vec<u8> {
+        match self.tips.split_first() {
+            Some((first, rest)) => revisions_input(first, rest, &[]),
+            None => Vec::new(),
+        }
+    }
+}
+
 /// Repository commit range information
 struct CommitRange<'repo> {
     /// The oldest (first) commit in the repository
@@ -127,1137 +475,7288 @@ struct CommitRange<'repo> {
     total_commits: u32,
 }
 
-/// A sample point in repository history
-struct SamplePoint {
-    /// Formatted date string (YYYY-MM-DD)
-    date: String,
-    /// Commit hash at this sample point
-    commit_hash: String,
-}
-
-/// Size measurement result
-struct SizeMeasurement {
-    /// Formatted date string (YYYY-MM-DD)
-    date: String,
-    /// Cumulative packed size in bytes
-    cumulative_size: u64,
-    /// Uncompressed blob size in bytes (if calculated)
-    uncompressed_size: Option<u64>,
+/// Time between two sample points
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interval {
+    Days(u32),
+    Weeks(u32),
+    /// Calendar months (the day of month is kept, or clamped to the month's end)
+    Months(u32),
+    /// Calendar years
+    Years(u32),
 }
 
-/// Number of days in a year (accounting for leap years)
-const DAYS_PER_YEAR: f64 = 365.25;
-/// Repository age threshold in years for using yearly sampling
-const YEARLY_THRESHOLD_YEARS: f64 = 6.0;
-/// Sampling interval in days for yearly sampling
-const YEARLY_INTERVAL_DAYS: i64 = 365;
-/// Sampling interval in days for monthly sampling
-const MONTHLY_INTERVAL_DAYS: i64 = 30;
-
-/// Check if the repository has a bitmap index available.
-///
-/// Bitmap indexes are stored in the objects/pack/ directory as .bitmap files.
-/// They significantly speed up git rev-list --disk-usage operations.
-fn check_bitmap_index(repo: &Repository) -> bool {
-    let objects_dir = repo.path().join("objects/pack");
-    if let Ok(entries) = std::fs::read_dir(objects_dir) {
-        for entry in entries.flatten() {
-            if entry.path().extension().is_some_and(|ext| ext == "bitmap") {
-                return true;
+impl Interval {
+    /// The `n`-th point after `start` (`start` itself for `n = 0`), if representable.
+    ///
+    /// Points are computed from `start` rather than from the previous point so
+    /// that month lengths do not make calendar intervals drift.
+    fn nth_after(&self, start: DateTime<Utc>, n: u32) -> Option<DateTime<Utc>> {
+        match *self {
+            Interval::Days(days) => {
+                start.checked_add_signed(Duration::days(i64::from(days) * i64::from(n)))
+            }
+            Interval::Weeks(weeks) => {
+                start.checked_add_signed(Duration::weeks(i64::from(weeks) * i64::from(n)))
+            }
+            Interval::Months(months) => {
+                start.checked_add_months(Months::new(months.checked_mul(n)?))
+            }
+            Interval::Years(years) => {
+                start.checked_add_months(Months::new(years.checked_mul(12)?.checked_mul(n)?))
             }
         }
     }
-    false
-}
-
-/// Get the first (oldest) and last (newest) commits from the repository.
-///
-/// This function walks the commit history using git2 and collects all commits
-/// with their timestamps for efficient binary search during sampling.
-/// Uses parallel processing for large repositories.
-fn get_commit_range<'a>(
-    repo: &'a Repository,
-    repo_path: &Path,
-    analysis_pb: &ProgressBar,
-) -> Result<CommitRange<'a>> {
-    // Check for bitmap index and warn if not present
-    let has_bitmap = check_bitmap_index(repo);
-    if !has_bitmap {
-        eprintln!(
-            "⚠️  Warning: No bitmap index found in repository.\n\
-             Running 'git repack -ad --write-bitmap-index' can significantly speed up size measurements.\n\
-             Example: cd {:?} && git repack -ad --write-bitmap-index",
-            repo_path
-        );
-    }
-
-    analysis_pb.set_message("Counting commits...");
 
-    // Get total commit count using git rev-list --count (fast, especially with bitmaps)
-    // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
-    let count_output = Command::new("git")
-        .arg("--no-replace-objects")
-        .arg("-C")
-        .arg(repo_path)
-        .args(["rev-list", "--count", "HEAD", "--"])
-        .output()?;
-    let total_commits = String::from_utf8_lossy(&count_output.stdout)
-        .trim()
-        .parse::<u32>()
-        .unwrap_or(0);
+    /// First calendar boundary of this interval at or after `time`.
+    ///
+    /// Boundaries are midnight UTC for days, Monday for weeks, the first of the
+    /// month for months and January 1st for years. Multi-unit intervals are
+    /// aligned on multiples counted from a fixed origin (e.g. `3mo` starts on
+    /// quarters), so that any two repositories get the same sample dates.
+    fn align(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        const SECONDS_PER_DAY: i64 = 86_400;
+        // 1970-01-05, the first Monday after the epoch
+        const FIRST_MONDAY: i64 = 4;
+
+        let day = time.timestamp().div_euclid(SECONDS_PER_DAY);
+        let start = match *self {
+            Interval::Days(days) => {
+                let aligned = day - day.rem_euclid(i64::from(days));
+                DateTime::from_timestamp(aligned * SECONDS_PER_DAY, 0)?
+            }
+            Interval::Weeks(weeks) => {
+                let since_monday = day - FIRST_MONDAY;
+                let aligned = day - since_monday.rem_euclid(7 * i64::from(weeks));
+                DateTime::from_timestamp(aligned * SECONDS_PER_DAY, 0)?
+            }
+            Interval::Months(months) => month_boundary(time, i64::from(months))?,
+            Interval::Years(years) => month_boundary(time, i64::from(years) * 12)?,
+        };
 
-    if total_commits == 0 {
-        return Err(GitSizeError::Validation(
-            "No commits found in repository".to_string(),
-        ));
+        if start < time {
+            self.nth_after(start, 1)
+        } else {
+            Some(start)
+        }
     }
+}
 
-    analysis_pb.set_message("Finding first and last commits...");
+/// First day of the period of `period` months containing `time`, with periods
+/// counted from January of year 0.
+fn month_boundary(time: DateTime<Utc>, period: i64) -> Option<DateTime<Utc>> {
+    let month = i64::from(time.year()) * 12 + i64::from(time.month0());
+    let aligned = month - month.rem_euclid(period);
+    Some(
+        NaiveDate::from_ymd_opt(
+            i32::try_from(aligned.div_euclid(12)).ok()?,
+            u32::try_from(aligned.rem_euclid(12)).ok()? + 1,
+            1,
+        )?
+        .and_hms_opt(0, 0, 0)?
+        .and_utc(),
+    )
+}
 
-    // Last commit is HEAD
-    let last_commit = repo.head()?.peel_to_commit()?;
+impl std::str::FromStr for Interval {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        let split = value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len());
+        let (count, unit) = value.split_at(split);
+        let count: u32 = count
+            .parse()
+            .ok()
+            .filter(|&count| count > 0)
+            .ok_or_else(|| format!("expected a positive count before the unit, got {:?}", value))?;
+        match unit {
+            "d" => Ok(Interval::Days(count)),
+            "w" => Ok(Interval::Weeks(count)),
+            "mo" => Ok(Interval::Months(count)),
+            "q" => count
+                .checked_mul(3)
+                .map(Interval::Months)
+                .ok_or_else(|| format!("interval too large: {:?}", value)),
+            "y" => Ok(Interval::Years(count)),
+            _ => Err(format!(
+                "unknown unit in {:?} (expected d, w, mo, q or y)",
+                value
+            )),
+        }
+    }
+}
 
-    // First commit: find all roots and pick the oldest
-    // SECURITY: Use streaming output (BufReader) to prevent OOM DoS if there are many root commits.
-    // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
-    let mut roots_child = Command::new("git")
-        .arg("--no-replace-objects")
-        .arg("-C")
-        .arg(repo_path)
-        .args(["rev-list", "--max-parents=0", "HEAD", "--"])
-        .stdout(Stdio::piped())
-        .spawn()?;
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interval::Days(count) => write!(f, "{}d", count),
+            Interval::Weeks(count) => write!(f, "{}w", count),
+            Interval::Months(count) => write!(f, "{}mo", count),
+            Interval::Years(count) => write!(f, "{}y", count),
+        }
+    }
+}
 
-    let roots_stdout = roots_child
-        .stdout
-        .take()
-        .ok_or_else(|| GitSizeError::Command("Failed to open git rev-list stdout".to_string()))?;
+/// Where sample points are placed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SampleBy {
+    /// Regular dates between the first and last commits
+    Date,
+    /// Every tag reachable from the analyzed history
+    Tags,
+}
 
-    let mut first_commit: Option<git2::Commit> = None;
-    let mut earliest_time = i64::MAX;
+/// Order of the CSV rows of tag sample points
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum TagSort {
+    /// Tag date (tagger date of annotated tags, commit date otherwise)
+    #[default]
+    Date,
+    /// Version number in the tag name (v1.2.3, 1.10.0-rc1, ...)
+    Semver,
+}
 
-    let mut reader = BufReader::new(roots_stdout);
-    let mut line = String::with_capacity(64);
-    while reader.read_line(&mut line)? > 0 {
-        if let Ok(oid) = git2::Oid::from_str(line.trim()) {
-            if let Ok(commit) = repo.find_commit(oid) {
-                let time = commit.time().seconds();
-                if time < earliest_time {
-                    earliest_time = time;
-                    first_commit = Some(commit);
-                }
-            }
+impl TagSort {
+    /// `rows` in the order they are written to the CSV.
+    ///
+    /// Sample points are measured and plotted in date order, so that added sizes
+    /// and the plot follow the history; `Semver` only reorders the written rows
+    /// by the version in their label.
+    fn csv_rows<T>(self, rows: &[T], label: impl Fn(&T) -> Option<&str>) -> Vec<&T> {
+        let mut rows: Vec<&T> = rows.iter().collect();
+        if self == TagSort::Semver {
+            rows.sort_by(|a, b| {
+                compare_versions(label(a).unwrap_or_default(), label(b).unwrap_or_default())
+            });
         }
-        line.clear();
+        rows
     }
-    let _ = roots_child.wait();
-
-    let first_commit = first_commit
-        .ok_or_else(|| GitSizeError::Validation("Failed to find initial commit".to_string()))?;
+}
 
-    Ok(CommitRange {
-        first_commit,
-        last_commit,
-        total_commits,
-    })
+/// Tag-based sampling options (`--sample-by tags`)
+#[derive(Default)]
+struct TagSampling {
+    /// Glob selecting the tags to sample (all tags if unset)
+    pattern: Option<String>,
+    /// Order of the CSV rows
+    sort: TagSort,
 }
 
-/// Generate sample points based on repository age.
-///
-/// This function determines a set of sampling dates between the first and last
-/// commits of the repository. It uses an adaptive strategy (yearly or monthly)
-/// unless forced by flags.
-fn generate_sample_points(
-    repo_path: &Path,
-    range: &CommitRange<'_>,
+/// Options controlling where sample points are placed
+#[derive(Default)]
+struct SamplingOptions {
+    /// Force monthly sampling
     monthly: bool,
+    /// Force yearly sampling
     yearly: bool,
-) -> Result<Vec<SamplePoint>> {
-    let first_time = range.first_commit.time().seconds();
-    let last_time = range.last_commit.time().seconds();
-
-    let first_dt = DateTime::from_timestamp(first_time, 0)
-        .ok_or_else(|| GitSizeError::Validation("Invalid first commit timestamp".to_string()))?
-        .with_timezone(&Utc);
-    let last_dt = DateTime::from_timestamp(last_time, 0)
-        .ok_or_else(|| GitSizeError::Validation("Invalid last commit timestamp".to_string()))?
-        .with_timezone(&Utc);
+    /// Explicit sampling interval, overriding the age-based heuristic
+    interval: Option<Interval>,
+    /// Number of evenly spaced sample points, instead of a fixed interval
+    samples: Option<u32>,
+    /// Align sample dates on calendar boundaries
+    calendar: bool,
+    /// Sample every Nth commit instead of sampling by date
+    every_n_commits: Option<u32>,
+    /// Sample at tags instead of regular dates
+    tags: Option<TagSampling>,
+    /// Unix timestamp before which no sample point is placed
+    since: Option<i64>,
+    /// Unix timestamp after which no sample point is placed
+    until: Option<i64>,
+}
 
-    let duration = last_dt - first_dt;
-    let years = duration.num_days() as f64 / DAYS_PER_YEAR;
+impl SamplingOptions {
+    /// Time span covered by the sample points: the commit range clamped to the
+    /// `since`/`until` bounds.
+    fn span(
+        &self,
+        history: &History,
+        range: &CommitRange<'_>,
+    ) -> Result<(DateTime<Utc>, DateTime<Utc>)> {
+        let first_time = history.commit_time(&range.first_commit);
+        let last_time = history.commit_time(&range.last_commit);
+        let start = self.since.map_or(first_time, |since| since.max(first_time));
+        let end = self.until.map_or(last_time, |until| until.min(last_time));
+
+        if start > end {
+            return Err(GitSizeError::Validation(
+                "No commits in the selected date range".to_string(),
+            ));
+        }
 
-    // Determine sampling strategy
-    let use_yearly = yearly || (!monthly && years > YEARLY_THRESHOLD_YEARS);
-    let interval_days = if use_yearly {
-        YEARLY_INTERVAL_DAYS
-    } else {
-        MONTHLY_INTERVAL_DAYS
-    };
+        let start_dt = DateTime::from_timestamp(start, 0).ok_or_else(|| {
+            GitSizeError::Validation(format!("Invalid start timestamp: {}", start))
+        })?;
+        let end_dt = DateTime::from_timestamp(end, 0)
+            .ok_or_else(|| GitSizeError::Validation(format!("Invalid end timestamp: {}", end)))?;
+        Ok((start_dt, end_dt))
+    }
 
-    let mut target_times = Vec::new();
-    let mut current_time = first_dt;
+    /// Whether the age-based heuristic picks yearly sampling for a span of `years`.
+    fn use_yearly(&self, years: f64) -> bool {
+        self.yearly || (!self.monthly && years > YEARLY_THRESHOLD_YEARS)
+    }
 
-    while current_time <= last_dt {
-        target_times.push(current_time);
-        current_time = match current_time.checked_add_signed(Duration::days(interval_days)) {
-            Some(new_time) => new_time,
-            None => break,
-        };
+    /// Sampling interval used for a span of `years`: the explicit interval, or
+    /// yearly for old repositories and monthly otherwise (calendar years and
+    /// months when aligned on the calendar).
+    fn interval(&self, years: f64) -> Interval {
+        match (self.interval, self.use_yearly(years), self.calendar) {
+            (Some(interval), _, _) => interval,
+            (None, true, false) => YEARLY_INTERVAL,
+            (None, false, false) => MONTHLY_INTERVAL,
+            (None, true, true) => Interval::Years(1),
+            (None, false, true) => Interval::Months(1),
+        }
     }
 
-    // Ensure the last commit's time is included as a target
-    if target_times.last().map(|t| t.timestamp()) != Some(last_dt.timestamp()) {
-        target_times.push(last_dt);
+    /// Human-readable sampling method for a span of `years`.
+    fn describe(&self, years: f64) -> String {
+        if let Some(tags) = &self.tags {
+            return format!(
+                "by tag{} ({} order)",
+                tags.pattern
+                    .as_ref()
+                    .map(|pattern| format!(" matching {:?}", pattern))
+                    .unwrap_or_default(),
+                match tags.sort {
+                    TagSort::Date => "date",
+                    TagSort::Semver => "semver",
+                }
+            );
+        }
+        if let Some(n) = self.every_n_commits {
+            return format!("every {} commits", n);
+        }
+        let method = match (self.samples, self.interval) {
+            (Some(count), _) => return format!("{} evenly spaced samples", count),
+            (None, Some(interval)) => format!("every {}", interval),
+            (None, None) if self.use_yearly(years) => "yearly".to_string(),
+            (None, None) => "monthly".to_string(),
+        };
+        if self.calendar {
+            format!("{}, calendar-aligned", method)
+        } else {
+            method
+        }
     }
 
-    // Sort target times DESCENDING because git rev-list is descending
-    target_times.sort_by_key(|t| Reverse(t.timestamp()));
+    /// Target timestamps of the sample points over `first..=last`.
+    ///
+    /// `last` itself is a target, except with `--calendar` where only calendar
+    /// boundaries are, so that the dates stay on the same grid from run to run.
+    fn target_times(&self, first: DateTime<Utc>, last: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+        let mut target_times: Vec<DateTime<Utc>> = match self.samples {
+            Some(count) => {
+                // The end of the span, appended below, is the last of the `count` points
+                let span = (last - first).num_seconds();
+                let steps = i64::from(count) - 1;
+                (0..steps)
+                    .filter_map(|i| {
+                        DateTime::from_timestamp(first.timestamp() + span * i / steps, 0)
+                    })
+                    .collect()
+            }
+            None => {
+                let years = (last - first).num_days() as f64 / DAYS_PER_YEAR;
+                let interval = self.interval(years);
+                let start = if self.calendar {
+                    interval.align(first)
+                } else {
+                    Some(first)
+                };
+                (0..)
+                    .map_while(|n| interval.nth_after(start?, n))
+                    .take_while(|time| *time <= last)
+                    .collect()
+            }
+        };
 
-    let mut sample_points = Vec::new();
+        // Ensure the end of the span is included as a target
+        let off_grid = self.samples.is_some() || !self.calendar;
+        if off_grid && target_times.last().map(|t| t.timestamp()) != Some(last.timestamp()) {
+            target_times.push(last);
+        }
+        target_times
+    }
+}
 
-    // Stream commits once to find all matches
-    // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
-    let mut child = Command::new("git")
-        .arg("--no-replace-objects")
-        .arg("-C")
-        .arg(repo_path)
-        .args(["rev-list", "--timestamp", "HEAD", "--"])
-        .stdout(Stdio::piped())
-        .spawn()
-        .map_err(|e| GitSizeError::Command(format!("Failed to spawn git rev-list: {}", e)))?;
+/// Size change between two sample points that triggers refinement
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Threshold {
+    /// Absolute size change in bytes
+    Bytes(u64),
+    /// Size change relative to the earlier sample point, in percent
+    Percent(f64),
+}
 
-    let stdout = child
-        .stdout
-        .take()
-        .ok_or_else(|| GitSizeError::Command("Failed to open git rev-list stdout".to_string()))?;
-    let mut reader = BufReader::new(stdout);
+impl Threshold {
+    /// Whether a size change of `change` bytes from `size` bytes exceeds the threshold.
+    fn exceeded(&self, size: u64, change: u64) -> bool {
+        match *self {
+            Threshold::Bytes(bytes) => change > bytes,
+            Threshold::Percent(percent) => change as f64 > size as f64 * percent / 100.0,
+        }
+    }
+}
 
-    let mut target_idx = 0;
+impl std::str::FromStr for Threshold {
+    type Err = String;
 
-    // Pre-format target dates and timestamps to avoid redundant work in the loop
-    let target_dates: Vec<String> = target_times
-        .iter()
-        .map(|t| t.format("%Y-%m-%d").to_string())
-        .collect();
-    let target_timestamps: Vec<i64> = target_times.iter().map(|t| t.timestamp()).collect();
-
-    let mut line = String::with_capacity(64);
-    while reader.read_line(&mut line)? > 0 {
-        // Use split_ascii_whitespace for better performance on git output
-        let mut parts = line.split_ascii_whitespace();
-        let ts = parts
-            .next()
-            .and_then(|s| s.parse::<i64>().ok())
-            .unwrap_or(0);
-        let hash = parts.next().unwrap_or("");
-
-        // While the current commit is at or before our current target timestamp,
-        // it's the latest commit for that target.
-        while target_idx < target_timestamps.len() && ts <= target_timestamps[target_idx] {
-            if !hash.is_empty() {
-                sample_points.push(SamplePoint {
-                    date: target_dates[target_idx].clone(),
-                    commit_hash: hash.to_string(),
-                });
-            }
-            target_idx += 1;
-        }
-
-        if target_idx >= target_timestamps.len() {
-            // Found all sample points, can stop early
-            let _ = child.kill();
-            break;
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value.strip_suffix('%') {
+            Some(percent) => percent
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|percent| percent.is_finite() && *percent >= 0.0)
+                .map(Threshold::Percent)
+                .ok_or_else(|| format!("invalid percentage: {:?}", value)),
+            None => parse_size(value).map(Threshold::Bytes),
         }
-        line.clear();
     }
+}
 
-    let _ = child.wait();
+/// Parse a size such as `1048576`, `500KB`, `1.5 GB` or `64MiB` into bytes.
+///
+/// Like `format_size`, `KB`/`MB`/`GB` are decimal units; `KiB`/`MiB`/`GiB` are
+/// binary ones.
+fn parse_size(value: &str) -> std::result::Result<u64, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        _ => return Err(format!("unknown size unit in {:?}", value)),
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+        .map(|number| (number * multiplier as f64).round() as u64)
+        .ok_or_else(|| format!("invalid size: {:?}", value))
+}
 
-    // Sort by date ascending for the rest of the application
-    sample_points.sort_by(|a, b| a.date.cmp(&b.date));
-    sample_points.dedup_by(|a, b| a.date == b.date);
+/// Adaptive refinement options (`--refine-threshold`)
+struct Refinement {
+    /// Size change between two sample points above which their interval is split
+    threshold: Threshold,
+    /// Intervals shorter than twice this are not split
+    min_interval: Interval,
+    /// Maximum total number of sample points
+    max_samples: usize,
+}
 
-    Ok(sample_points)
+/// A sample point in repository history
+struct SamplePoint {
+    /// Formatted date string (YYYY-MM-DD)
+    date: String,
+    /// Commit hash at this sample point
+    commit_hash: String,
+    /// Date of `commit_hash` (Unix timestamp, according to the date source)
+    commit_time: i64,
+    /// Other ref tips measured together with `commit_hash` (whole-repository mode)
+    extra_tips: Vec<String>,
+    /// Name of the sample point (tag name with `--sample-by tags`)
+    label: Option<String>,
+    /// 1-based position of the commit in the history (with `--every-n-commits`)
+    commit_index: Option<u64>,
 }
 
-/// Calculate the size of objects reachable from a specific commit.
-///
-/// This function uses git commands via `std::process::Command` to:
-/// 1. Measure the packed disk usage using `git rev-list --objects --disk-usage`.
-/// 2. (Optional) Measure the uncompressed size of all blobs using a pipeline
-///    of `git rev-list` and `git cat-file`.
-fn measure_size_at_commit(
-    source_repo: &Path,
-    commit_hash: &str,
-    debug: bool,
-    calculate_uncompressed: bool,
-) -> Result<(u64, Option<u64>)> {
-    // Basic validation
-    if commit_hash.is_empty() {
-        return Err(GitSizeError::Validation(
-            "Commit hash cannot be empty".to_string(),
-        ));
+/// Size measurement result
+struct SizeMeasurement {
+    /// Formatted date string (YYYY-MM-DD)
+    date: String,
+    /// Commit measured (the newest tip in whole-repository mode)
+    commit_hash: String,
+    /// Date of `commit_hash` (Unix timestamp)
+    commit_time: i64,
+    /// Name of the sample point (tag name with `--sample-by tags`)
+    label: Option<String>,
+    /// 1-based position of the commit in the history (with `--every-n-commits`)
+    commit_index: Option<u64>,
+    /// Cumulative packed size in bytes
+    cumulative_size: u64,
+    /// Uncompressed blob size in bytes (if calculated)
+    uncompressed_size: Option<u64>,
+    /// Sizes and counts per object type (if calculated)
+    object_stats: Option<ObjectStats>,
+    /// Packed size of the objects added since the previous sample point (if calculated)
+    added_size: Option<u64>,
+}
+
+impl SizeMeasurement {
+    /// Name of the sample point in reports: its label if any, else its date
+    fn name(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.date)
     }
+}
 
-    // Get packed disk usage using git rev-list --disk-usage
-    // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
-    let disk_usage_output = Command::new("git")
-        .arg("--no-replace-objects")
-        .arg("-C")
-        .arg(source_repo)
-        .args([
-            "rev-list",
-            "--objects",
-            "--disk-usage",
-            "--use-bitmap-index",
-            commit_hash,
-            "--",
-        ])
-        .output()
-        .map_err(|e| GitSizeError::Command(format!("Failed to get disk usage: {}", e)))?;
+/// Per-object-type totals
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct TypeTotals {
+    commits: u64,
+    trees: u64,
+    blobs: u64,
+    tags: u64,
+}
 
-    if !disk_usage_output.status.success() {
-        return Err(GitSizeError::Command(
-            "Failed to get disk usage".to_string(),
-        ));
+impl TypeTotals {
+    /// Add `value` to the total of `object_type` (as reported by `git cat-file`).
+    fn add(&mut self, object_type: &str, value: u64) {
+        match object_type {
+            "commit" => self.commits += value,
+            "tree" => self.trees += value,
+            "blob" => self.blobs += value,
+            "tag" => self.tags += value,
+            _ => {}
+        }
     }
 
-    // The last line contains the total disk usage in bytes
-    // Optimized: avoid String allocation and correctly handle potential multi-line output
-    let packed_size = std::str::from_utf8(&disk_usage_output.stdout)
-        .ok()
-        .and_then(|s| s.trim().lines().last())
-        .and_then(|s| s.trim().parse::<u64>().ok())
-        .unwrap_or(0);
-
-    // Calculate uncompressed size only if requested (it's slower)
-    let uncompressed_size = if calculate_uncompressed {
-        // Optimized: use --filter=object:type=blob to skip trees, and --no-object-names to reduce output.
-        // Also use OS-level piping between processes to avoid the overhead of a manual Rust pipe thread.
-        // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
-        let mut rev_list = Command::new("git")
-            .arg("--no-replace-objects")
-            .arg("-C")
-            .arg(source_repo)
-            .args([
-                "rev-list",
-                "--objects",
-                "--filter=object:type=blob",
-                "--no-object-names",
-                "--use-bitmap-index",
-                commit_hash,
-                "--",
-            ])
-            .stdout(Stdio::piped())
-            .spawn()
-            .map_err(|e| GitSizeError::Command(format!("Failed to spawn git rev-list: {}", e)))?;
+    /// Totals in CSV column order: commits, trees, blobs, tags
+    fn values(&self) -> [u64; 4] {
+        [self.commits, self.trees, self.blobs, self.tags]
+    }
 
-        let rev_list_stdout = rev_list.stdout.take().ok_or_else(|| {
-            GitSizeError::Command("Failed to open git rev-list stdout".to_string())
-        })?;
+    /// Parse totals from the next four CSV fields, in `values()` order.
+    fn parse<'a>(fields: &mut impl Iterator<Item = &'a str>) -> Option<TypeTotals> {
+        let mut next = || fields.next()?.parse().ok();
+        Some(TypeTotals {
+            commits: next()?,
+            trees: next()?,
+            blobs: next()?,
+            tags: next()?,
+        })
+    }
+}
 
-        let mut cat_file = Command::new("git")
-            .arg("--no-replace-objects")
-            .arg("-C")
-            .arg(source_repo)
-            .args(["cat-file", "--batch-check=%(objecttype) %(objectsize)"])
-            .stdin(Stdio::from(rev_list_stdout))
-            .stdout(Stdio::piped())
-            .spawn()
-            .map_err(|e| GitSizeError::Command(format!("Failed to spawn git cat-file: {}", e)))?;
+/// Statistics of the objects reachable at a sample point, split by object type
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct ObjectStats {
+    /// Packed (on-disk) size per type in bytes
+    packed: TypeTotals,
+    /// Uncompressed size per type in bytes
+    uncompressed: TypeTotals,
+    /// Number of objects per type
+    counts: TypeTotals,
+}
 
-        let stdout = cat_file.stdout.take().ok_or_else(|| {
-            GitSizeError::Command("Failed to open git cat-file stdout".to_string())
-        })?;
+/// A blob introduced between two sample points (`--top-blobs`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IntroducedBlob {
+    /// Date of the earlier sample point (YYYY-MM-DD)
+    start_date: String,
+    /// Date of the later sample point (YYYY-MM-DD)
+    end_date: String,
+    /// Blob object id
+    blob: String,
+    /// Path the blob was found at
+    path: String,
+    /// Uncompressed size in bytes
+    size: u64,
+    /// Packed (on-disk) size in bytes
+    disk_size: u64,
+    /// Oldest commit of the interval that added the blob, if found
+    commit: Option<String>,
+}
 
-        let mut total = 0u64;
-        let mut blob_count = 0u64;
-        let mut object_count = 0u64;
+/// Optional column groups of the CSV output
+#[derive(Debug, Default, Clone, Copy)]
+struct CsvColumns {
+    /// `commit` and `commit-date` columns, with the date column named `target-date`
+    /// (unless `--no-commit-columns`)
+    commit: bool,
+    /// `label` column (`--sample-by tags`)
+    label: bool,
+    /// `commit-index` column (`--every-n-commits`)
+    commit_index: bool,
+    /// `uncompressed-size` column (`-U`)
+    uncompressed: bool,
+    /// `added-size` column (`--added`)
+    added: bool,
+    /// Per-object-type size columns (`--by-type`)
+    object_types: bool,
+    /// Object count columns (`--counts`)
+    counts: bool,
+}
 
-        let mut reader = BufReader::new(stdout);
-        let mut line = String::with_capacity(128);
-        while reader.read_line(&mut line)? > 0 {
-            object_count += 1;
-            // Optimized: use split_ascii_whitespace and reused buffer
-            // git cat-file --batch-check output is "<type> <size>"
-            let mut parts = line.split_ascii_whitespace();
-            if let Some("blob") = parts.next() {
-                if let Some(size_str) = parts.next() {
-                    if let Ok(s_u64) = size_str.parse::<u64>() {
-                        total += s_u64;
-                        blob_count += 1;
-                    }
-                }
-            }
-            line.clear();
+impl CsvColumns {
+    /// CSV header for the selected columns
+    fn header(&self) -> Vec<&'static str> {
+        let mut header = if self.commit {
+            vec!["target-date", "commit", "commit-date"]
+        } else {
+            vec!["date"]
+        };
+        if self.label {
+            header.push("label");
         }
+        if self.commit_index {
+            header.push("commit-index");
+        }
+        header.push("cumulative-size");
+        if self.uncompressed {
+            header.push("uncompressed-size");
+        }
+        if self.added {
+            header.push("added-size");
+        }
+        if self.object_types {
+            header.extend([
+                "commits-size",
+                "trees-size",
+                "blobs-size",
+                "tags-size",
+                "commits-uncompressed-size",
+                "trees-uncompressed-size",
+                "blobs-uncompressed-size",
+                "tags-uncompressed-size",
+            ]);
+        }
+        if self.counts {
+            header.extend([
+                "object-count",
+                "commit-count",
+                "tree-count",
+                "blob-count",
+                "tag-count",
+            ]);
+        }
+        header
+    }
 
-        // Clean up processes
-        cat_file.wait().map_err(|e| {
-            GitSizeError::Command(format!("Failed to wait for git cat-file: {}", e))
-        })?;
-        rev_list.wait().map_err(|e| {
-            GitSizeError::Command(format!("Failed to wait for git rev-list: {}", e))
-        })?;
-
-        if debug {
-            println!("  Objects: {}, Blobs: {}", object_count, blob_count);
-            println!(
-                "  Packed size: {}, Uncompressed size: {}",
-                format_size(packed_size),
-                format_size(total)
+    /// CSV record of a measurement, matching `header()`
+    fn record(&self, data: &SizeMeasurement) -> Vec<String> {
+        let mut record = vec![data.date.clone()];
+        if self.commit {
+            record.push(data.commit_hash.clone());
+            record.push(format_commit_date(data.commit_time));
+        }
+        if self.label {
+            record.push(data.label.clone().unwrap_or_default());
+        }
+        if self.commit_index {
+            record.push(data.commit_index.unwrap_or(0).to_string());
+        }
+        record.push(data.cumulative_size.to_string());
+        if self.uncompressed {
+            record.push(data.uncompressed_size.unwrap_or(0).to_string());
+        }
+        if self.added {
+            record.push(data.added_size.unwrap_or(0).to_string());
+        }
+        let stats = data.object_stats.unwrap_or_default();
+        if self.object_types {
+            record.extend(
+                stats
+                    .packed
+                    .values()
+                    .iter()
+                    .chain(stats.uncompressed.values().iter())
+                    .map(|v| v.to_string()),
             );
         }
-
-        Some(total)
-    } else {
-        if debug {
-            println!("  Packed size: {}", format_size(packed_size));
+        if self.counts {
+            let counts = stats.counts;
+            record.push(counts.values().iter().sum::<u64>().to_string());
+            record.extend(counts.values().iter().map(|v| v.to_string()));
         }
-        None
-    };
+        record
+    }
 
-    Ok((packed_size, uncompressed_size))
+    /// Parse a CSV record written by `record()`.
+    fn parse(&self, record: &csv::StringRecord) -> Option<SizeMeasurement> {
+        let mut fields = record.iter();
+        let date = fields.next()?.to_string();
+        let (commit_hash, commit_time) = if self.commit {
+            let commit_hash = fields.next()?.to_string();
+            let commit_time = DateTime::parse_from_rfc3339(fields.next()?)
+                .ok()?
+                .timestamp();
+            (commit_hash, commit_time)
+        } else {
+            (String::new(), 0)
+        };
+        let label = if self.label {
+            Some(fields.next()?.to_string()).filter(|label| !label.is_empty())
+        } else {
+            None
+        };
+        let commit_index = if self.commit_index {
+            Some(fields.next()?.parse().ok()?)
+        } else {
+            None
+        };
+        let cumulative_size = fields.next()?.parse().ok()?;
+        let uncompressed_size = if self.uncompressed {
+            Some(fields.next()?.parse().ok()?)
+        } else {
+            None
+        };
+        let added_size = if self.added {
+            Some(fields.next()?.parse().ok()?)
+        } else {
+            None
+        };
+        let mut stats = ObjectStats::default();
+        if self.object_types {
+            stats.packed = TypeTotals::parse(&mut fields)?;
+            stats.uncompressed = TypeTotals::parse(&mut fields)?;
+        }
+        if self.counts {
+            // The total object count comes first
+            fields.next()?;
+            stats.counts = TypeTotals::parse(&mut fields)?;
+        }
+        Some(SizeMeasurement {
+            date,
+            commit_hash,
+            commit_time,
+            label,
+            commit_index,
+            cumulative_size,
+            uncompressed_size,
+            object_stats: (self.object_types || self.counts).then_some(stats),
+            added_size,
+        })
+    }
 }
 
-/// Format a byte count into a human-readable string (B, KB, MB, GB).
-///
-/// This function converts a size in bytes to a human-readable format
-/// using decimal prefixes (1 KB = 1000 bytes).
-///
-/// # Arguments
-///
-/// * `size` - The size in bytes to format
-///
-/// # Examples
-///
-/// ```
-/// assert_eq!(format_size(0), "0 B");
-/// assert_eq!(format_size(1500), "1.50 KB");
-/// assert_eq!(format_size(2500000), "2.50 MB");
-/// assert_eq!(format_size(5500000000), "5.50 GB");
-/// ```
-fn format_size(size: u64) -> String {
-    const KB: u64 = 1_000;
-    const MB: u64 = 1_000_000;
-    const GB: u64 = 1_000_000_000;
+/// Reachable blob sizes attributed to one group of a breakdown (e.g. a directory)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct GroupSize {
+    /// Packed (on-disk) size in bytes
+    packed: u64,
+    /// Uncompressed size in bytes
+    uncompressed: u64,
+}
 
-    if size >= GB {
-        format!("{:.2} GB", size as f64 / GB as f64)
-    } else if size >= MB {
-        format!("{:.2} MB", size as f64 / MB as f64)
-    } else if size >= KB {
-        format!("{:.2} KB", size as f64 / KB as f64)
-    } else {
-        format!("{} B", size)
-    }
+/// How reachable blobs are grouped in breakdown mode
+enum Grouping {
+    /// By directory, truncated to the given number of path components
+    Directory(usize),
+    /// By lowercased file extension
+    Extension,
+    /// By the category of the first matching `(glob, category)` pair
+    Categories(Vec<(String, String)>),
 }
 
-/// Generate a cumulative size over time plot using the `plotters` library.
-///
-/// This creates a PNG file at `output_path` displaying repository growth
-/// based on the provided size measurement data.
-fn generate_plot(data: &[SizeMeasurement], output_path: &Path) -> Result<()> {
-    if data.is_empty() {
-        return Ok(());
+impl Grouping {
+    /// Name of the group column in the breakdown CSV
+    fn column(&self) -> &'static str {
+        match self {
+            Grouping::Directory(_) => "path",
+            Grouping::Extension => "extension",
+            Grouping::Categories(_) => "category",
+        }
     }
 
-    let plot_data: Vec<(i64, u64)> = data
-        .iter()
-        .filter_map(|d| {
-            NaiveDate::parse_from_str(&d.date, "%Y-%m-%d")
-                .ok()
-                .and_then(|dt| {
-                    dt.and_hms_opt(0, 0, 0)
-                        .map(|naive| naive.and_utc().timestamp())
-                        .map(|ts| (ts, d.cumulative_size))
-                })
-        })
-        .collect();
-
-    if plot_data.is_empty() {
-        return Ok(());
+    /// Group a blob belongs to, given its path.
+    fn group_of<'a>(&'a self, path: &'a str) -> Cow<'a, str> {
+        match self {
+            Grouping::Directory(depth) => Cow::Borrowed(path_prefix(path, *depth)),
+            Grouping::Extension => match file_extension(path) {
+                Some(ext) if ext.bytes().any(|b| b.is_ascii_uppercase()) => {
+                    Cow::Owned(format!(".{}", ext.to_ascii_lowercase()))
+                }
+                // Point back into the path to include the dot without allocating
+                Some(ext) => Cow::Borrowed(&path[path.len() - ext.len() - 1..]),
+                None => Cow::Borrowed(NO_EXTENSION_GROUP),
+            },
+            Grouping::Categories(categories) => Cow::Borrowed(
+                categories
+                    .iter()
+                    .find(|(pattern, _)| glob_match(pattern, path))
+                    .map_or(UNCATEGORIZED_GROUP, |(_, category)| category.as_str()),
+            ),
+        }
     }
+}
 
-    let min_ts = plot_data.iter().map(|(t, _)| *t).min().unwrap_or(0);
-    let max_ts = plot_data.iter().map(|(t, _)| *t).max().unwrap_or(0);
-    let max_size = plot_data.iter().map(|(_, s)| *s).max().unwrap_or(0);
-
-    // Add margins
-    let time_margin = ((max_ts - min_ts) / 20).max(86400 * 30);
-    let size_margin = (max_size / 10).max(1000);
+/// Size breakdown of the blobs reachable at a sample point
+struct Breakdown {
+    /// Formatted date string (YYYY-MM-DD)
+    date: String,
+    /// Commit measured (the newest tip in whole-repository mode)
+    commit_hash: String,
+    /// Date of `commit_hash` (Unix timestamp)
+    commit_time: i64,
+    /// Name of the sample point (tag name with `--sample-by tags`)
+    label: Option<String>,
+    /// 1-based position of the commit in the history (with `--every-n-commits`)
+    commit_index: Option<u64>,
+    /// Sizes per group, keyed by group name
+    groups: BTreeMap<String, GroupSize>,
+}
 
-    let root = BitMapBackend::new(output_path, (1200, 600)).into_drawing_area();
-    root.fill(&WHITE)
+/// Number of days in a year (accounting for leap years)
+const DAYS_PER_YEAR: f64 = 365.25;
+/// Repository age threshold in years for using yearly sampling
+const YEARLY_THRESHOLD_YEARS: f64 = 6.0;
+/// Sampling interval for yearly sampling
+const YEARLY_INTERVAL: Interval = Interval::Days(365);
+/// Sampling interval for monthly sampling
+const MONTHLY_INTERVAL: Interval = Interval::Days(30);
+/// Maximum number of groups drawn as separate bands in breakdown plots
+const MAX_PLOT_GROUPS: usize = 8;
+/// Shortest interval split by adaptive refinement unless `--min-interval` is given
+const DEFAULT_MIN_INTERVAL: Interval = Interval::Days(1);
+/// Sample budget of adaptive refinement unless `--max-samples` is given
+const DEFAULT_MAX_SAMPLES: usize = 200;
+/// Maximum number of sample point labels (tag names) drawn on plots
+const MAX_PLOT_LABELS: usize = 40;
+/// Group of files without an extension when grouping by extension
+const NO_EXTENSION_GROUP: &str = "(none)";
+/// Group of files matching no pattern when grouping by category
+const UNCATEGORIZED_GROUP: &str = "other";
+/// Default measurement cache directory, inside the git directory
+const CACHE_DIR_NAME: &str = "size-history";
+
+/// Check if the repository has a bitmap index available.
+///
+/// Bitmap indexes are stored in the objects/pack/ directory as .bitmap files.
+/// They significantly speed up git rev-list --disk-usage operations.
+fn check_bitmap_index(repo: &Repository) -> bool {
+    let objects_dir = repo.path().join("objects/pack");
+    if let Ok(entries) = std::fs::read_dir(objects_dir) {
+        for entry in entries.flatten() {
+            if entry.path().extension().is_some_and(|ext| ext == "bitmap") {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Build a `git` command operating on the repository at `git_dir`.
+///
+/// The git directory is passed with `--git-dir` (rather than `-C <worktree>`) so
+/// that bare repositories and mirrors work exactly like regular checkouts.
+/// Replace refs are disabled so the raw repository state is analyzed.
+fn git_command(git_dir: &Path) -> Command {
+    let mut command = Command::new("git");
+    command
+        .arg("--no-replace-objects")
+        .arg("--git-dir")
+        .arg(git_dir);
+    command
+}
+
+/// Revisions given to git on its standard input (with `--stdin`): a commit and
+/// the other tips measured with it, then the `exclude` commits as `^<commit>` lines.
+///
+/// Whole-repository mode may have tens of thousands of ref tips, which would
+/// not fit on a command line.
+fn revisions_input(commit_hash: &str, extra_tips: &[String], exclude: &[String]) -> Vec<u8> {
+    let mut input = String::new();
+    for hash in std::iter::once(commit_hash).chain(extra_tips.iter().map(String::as_str)) {
+        input.push_str(hash);
+        input.push('\n');
+    }
+    for hash in exclude {
+        input.push('^');
+        input.push_str(hash);
+        input.push('\n');
+    }
+    input.into_bytes()
+}
+
+/// Ids of the running git children, killed if the run is interrupted
+static RUNNING_CHILDREN: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+/// Set once the run has been interrupted (Ctrl-C or SIGTERM)
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// Exit code of an interrupted run (128 + SIGINT, as with shells)
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Whether the run has been interrupted
+fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Stop with `GitSizeError::Interrupted` once the run has been interrupted.
+fn check_interrupted() -> Result<()> {
+    if interrupted() {
+        Err(GitSizeError::Interrupted)
+    } else {
+        Ok(())
+    }
+}
+
+/// Handle Ctrl-C and SIGTERM: no new work is started, and the running git
+/// children are killed so that the run winds down quickly instead of leaving
+/// them behind. A second signal exits immediately.
+fn install_interrupt_handler() {
+    let result = ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+        eprintln!("\nInterrupted, stopping git processes...");
+        if let Ok(running) = RUNNING_CHILDREN.lock() {
+            for &pid in running.iter() {
+                kill_process(pid);
+            }
+        }
+    });
+    if let Err(e) = result {
+        eprintln!("⚠️  Warning: Cannot handle Ctrl-C: {}", e);
+    }
+}
+
+/// Kill a running child process.
+#[cfg(unix)]
+fn kill_process(pid: u32) {
+    if let Ok(pid) = libc::pid_t::try_from(pid) {
+        // SAFETY: kill(2) does not access memory. The pid is that of a child
+        // which is still registered. Children are unregistered after they exit
+        // but before they are reaped (see `TrackedChild::wait`), so the pid
+        // cannot have been reused by another process.
+        unsafe {
+            libc::kill(pid, libc::SIGKILL);
+        }
+    }
+}
+
+/// Kill a running child process.
+#[cfg(not(unix))]
+fn kill_process(_pid: u32) {
+    // Console Ctrl-C events are delivered to every process attached to the
+    // console, git children included
+}
+
+/// Wait for a child to exit without reaping it, so that its pid stays reserved.
+#[cfg(unix)]
+fn wait_exited(child: &Child) -> io::Result<()> {
+    let pid = libc::id_t::from(child.id());
+    loop {
+        // SAFETY: waitid(2) only writes to `info`, a valid siginfo_t
+        let result = unsafe {
+            let mut info: libc::siginfo_t = std::mem::zeroed();
+            libc::waitid(libc::P_PID, pid, &mut info, libc::WEXITED | libc::WNOWAIT)
+        };
+        if result == 0 {
+            return Ok(());
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+}
+
+/// Wait for a child to exit without reaping it.
+#[cfg(not(unix))]
+fn wait_exited(_child: &Child) -> io::Result<()> {
+    // Children are never killed by pid (see `kill_process`)
+    Ok(())
+}
+
+/// A git child process, tracked so that it is killed if the run is interrupted
+/// before it is waited for.
+struct TrackedChild {
+    child: Child,
+    /// Whether the child is still registered in `RUNNING_CHILDREN`
+    tracked: bool,
+}
+
+impl TrackedChild {
+    /// Wait for the child to exit, like `Child::wait`.
+    fn wait(&mut self) -> io::Result<ExitStatus> {
+        if self.tracked {
+            // Unregistered once exited but before being reaped: until then its
+            // pid cannot be reused, so the interrupt handler never kills another
+            // process
+            wait_exited(&self.child)?;
+            self.untrack();
+        }
+        self.child.wait()
+    }
+
+    /// Remove the child from the running children.
+    fn untrack(&mut self) {
+        if std::mem::take(&mut self.tracked) {
+            if let Ok(mut running) = RUNNING_CHILDREN.lock() {
+                running.retain(|&pid| pid != self.child.id());
+            }
+        }
+    }
+}
+
+impl TrackedChild {
+    /// Collect the output of a child spawned with piped stdout and stderr, like
+    /// `Child::wait_with_output`.
+    fn wait_with_output(mut self) -> io::Result<Output> {
+        // Read stderr on another thread so that neither pipe can fill up and block git
+        let stderr = self.stderr.take().map(|mut pipe| {
+            std::thread::spawn(move || {
+                let mut stderr = Vec::new();
+                let _ = pipe.read_to_end(&mut stderr);
+                stderr
+            })
+        });
+        let mut stdout = Vec::new();
+        if let Some(mut pipe) = self.stdout.take() {
+            pipe.read_to_end(&mut stdout)?;
+        }
+        let status = self.wait()?;
+        let stderr = stderr
+            .and_then(|reader| reader.join().ok())
+            .unwrap_or_default();
+        Ok(Output {
+            status,
+            stdout,
+            stderr,
+        })
+    }
+}
+
+impl std::ops::Deref for TrackedChild {
+    type Target = Child;
+
+    fn deref(&self) -> &Child {
+        &self.child
+    }
+}
+
+impl std::ops::DerefMut for TrackedChild {
+    fn deref_mut(&mut self) -> &mut Child {
+        &mut self.child
+    }
+}
+
+impl Drop for TrackedChild {
+    fn drop(&mut self) {
+        // Children that are not waited for are killed, rather than left running
+        if self.tracked {
+            let _ = self.child.kill();
+            let _ = self.wait();
+        }
+    }
+}
+
+/// Running commands as tracked children (see `TrackedChild`)
+trait SpawnTracked {
+    /// Spawn the command, like `Command::spawn`. Fails once the run is interrupted.
+    fn spawn_tracked(&mut self) -> io::Result<TrackedChild>;
+
+    /// Spawn the command with `input` written to its standard input.
+    fn spawn_with_input(&mut self, input: Vec<u8>) -> io::Result<TrackedChild>;
+
+    /// Run the command and collect its output, like `Command::output`.
+    fn output_tracked(&mut self) -> io::Result<Output>;
+
+    /// Run the command with `input` written to its standard input, and collect its output.
+    fn output_with_input(&mut self, input: Vec<u8>) -> io::Result<Output>;
+}
+
+impl SpawnTracked for Command {
+    fn spawn_tracked(&mut self) -> io::Result<TrackedChild> {
+        // Holding the lock while spawning: an interruption either happens
+        // before (and is seen here) or kills the registered child
+        let mut running = RUNNING_CHILDREN
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if interrupted() {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "Interrupted"));
+        }
+        let child = self.spawn()?;
+        running.push(child.id());
+        Ok(TrackedChild {
+            child,
+            tracked: true,
+        })
+    }
+
+    fn spawn_with_input(&mut self, input: Vec<u8>) -> io::Result<TrackedChild> {
+        let mut child = self.stdin(Stdio::piped()).spawn_tracked()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // Written on another thread so that git never waits for its output to
+            // be read while its input is being written. A git that fails early
+            // closes its end of the pipe, and the failure shows in its exit status.
+            std::thread::spawn(move || {
+                let _ = stdin.write_all(&input);
+            });
+        }
+        Ok(child)
+    }
+
+    fn output_tracked(&mut self) -> io::Result<Output> {
+        self.stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn_tracked()?
+            .wait_with_output()
+    }
+
+    fn output_with_input(&mut self, input: Vec<u8>) -> io::Result<Output> {
+        self.stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn_with_input(input)?
+            .wait_with_output()
+    }
+}
+
+/// Resolve a revision expression (ref name, tag, `main~10`, ...) to a commit.
+///
+/// Revisions are always resolved with git2 so that only a validated commit id
+/// is ever passed on to the git CLI.
+fn resolve_revision<'a>(repo: &'a Repository, rev: &str) -> Result<git2::Commit<'a>> {
+    repo.revparse_single(rev)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|e| GitSizeError::Validation(format!("Invalid revision {:?}: {}", rev, e)))
+}
+
+/// Get the first (oldest) and last (newest) commits of the analyzed history.
+///
+/// This function walks the commit history using git2 and collects all commits
+/// with their timestamps for efficient binary search during sampling.
+/// Uses parallel processing for large repositories.
+fn get_commit_range<'a>(
+    repo: &'a Repository,
+    history: &History,
+    analysis_pb: &ProgressBar,
+) -> Result<CommitRange<'a>> {
+    let git_dir = repo.path();
+
+    // Check for bitmap index and warn if not present
+    let has_bitmap = check_bitmap_index(repo);
+    if !has_bitmap {
+        eprintln!(
+            "⚠️  Warning: No bitmap index found in repository.\n\
+             Running 'git repack -ad --write-bitmap-index' can significantly speed up size measurements.\n\
+             Example: git --git-dir {:?} repack -ad --write-bitmap-index",
+            git_dir
+        );
+    }
+
+    // Last commit is the newest of the analyzed tips
+    let mut last_commit: Option<git2::Commit> = None;
+    for tip in &history.tips {
+        let commit = repo.find_commit(git2::Oid::from_str(tip)?)?;
+        if last_commit.as_ref().map_or(true, |last| {
+            history.commit_time(&commit) > history.commit_time(last)
+        }) {
+            last_commit = Some(commit);
+        }
+    }
+    let last_commit = last_commit
+        .ok_or_else(|| GitSizeError::Validation("No commits found in repository".to_string()))?;
+
+    analysis_pb.set_message("Counting commits...");
+
+    // Get total commit count using git rev-list --count (fast, especially with bitmaps)
+    // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
+    let count_output = git_command(git_dir)
+        .args(["rev-list", "--count"])
+        .args(history.rev_list_args())
+        .arg("--")
+        .output_with_input(history.tips_input())?;
+    let total_commits = String::from_utf8_lossy(&count_output.stdout)
+        .trim()
+        .parse::<u32>()
+        .unwrap_or(0);
+
+    if total_commits == 0 {
+        return Err(GitSizeError::Validation(
+            "No commits found in repository".to_string(),
+        ));
+    }
+
+    analysis_pb.set_message("Finding first commit...");
+
+    // First commit: find all roots and pick the oldest
+    // SECURITY: Use streaming output (BufReader) to prevent OOM DoS if there are many root commits.
+    // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
+    let mut roots_child = git_command(git_dir)
+        .args(["rev-list", "--max-parents=0"])
+        .args(history.rev_list_args())
+        .arg("--")
+        .stdout(Stdio::piped())
+        .spawn_with_input(history.tips_input())?;
+
+    let roots_stdout = roots_child
+        .stdout
+        .take()
+        .ok_or_else(|| GitSizeError::Command("Failed to open git rev-list stdout".to_string()))?;
+
+    let mut first_commit: Option<git2::Commit> = None;
+    let mut earliest_time = i64::MAX;
+
+    let mut reader = BufReader::new(roots_stdout);
+    let mut line = String::with_capacity(64);
+    while reader.read_line(&mut line)? > 0 {
+        if let Ok(oid) = git2::Oid::from_str(line.trim()) {
+            if let Ok(commit) = repo.find_commit(oid) {
+                let time = history.commit_time(&commit);
+                if time < earliest_time {
+                    earliest_time = time;
+                    first_commit = Some(commit);
+                }
+            }
+        }
+        line.clear();
+    }
+    let _ = roots_child.wait();
+
+    let first_commit = first_commit
+        .ok_or_else(|| GitSizeError::Validation("Failed to find initial commit".to_string()))?;
+
+    Ok(CommitRange {
+        first_commit,
+        last_commit,
+        total_commits,
+    })
+}
+
+/// Parse a `--since`/`--until` date into a Unix timestamp.
+///
+/// Plain `YYYY-MM-DD` dates are taken as midnight UTC, RFC 3339 timestamps are
+/// used as-is and relative dates (`2.years.ago`, `3 weeks ago`) count back from
+/// now. Anything else is rejected rather than guessed at, so that a typo does
+/// not silently select the wrong range.
+fn parse_date(value: &str) -> Result<i64> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        if let Some(datetime) = date.and_hms_opt(0, 0, 0) {
+            return Ok(datetime.and_utc().timestamp());
+        }
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Ok(datetime.timestamp());
+    }
+
+    relative_date(value, Utc::now())
+        .map(|datetime| datetime.timestamp())
+        .ok_or_else(|| {
+            GitSizeError::Validation(format!(
+                "Invalid date {:?}: expected YYYY-MM-DD, an RFC 3339 timestamp or a \
+                 relative date such as 2.years.ago",
+                value
+            ))
+        })
+}
+
+/// Resolve a relative date `<count>.<unit>.ago` (or `<count> <unit> ago`) counting
+/// back from `now`, with units from seconds to years.
+fn relative_date(value: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let mut parts = value
+        .split(|c: char| c == '.' || c.is_ascii_whitespace())
+        .filter(|part| !part.is_empty());
+    let (Some(count), Some(unit), Some("ago"), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    let count: u32 = count.parse().ok()?;
+    let count_i64 = i64::from(count);
+    match unit.strip_suffix('s').unwrap_or(unit) {
+        "second" => now.checked_sub_signed(Duration::seconds(count_i64)),
+        "minute" => now.checked_sub_signed(Duration::minutes(count_i64)),
+        "hour" => now.checked_sub_signed(Duration::hours(count_i64)),
+        "day" => now.checked_sub_signed(Duration::days(count_i64)),
+        "week" => now.checked_sub_signed(Duration::weeks(count_i64)),
+        "month" => now.checked_sub_months(Months::new(count)),
+        "year" => now.checked_sub_months(Months::new(count.checked_mul(12)?)),
+        _ => None,
+    }
+}
+
+/// Generate sample points based on repository age.
+///
+/// This function determines a set of sampling dates between the first and last
+/// commits of the repository, clamped to the `since`/`until` bounds. It uses an
+/// adaptive strategy (yearly or monthly) unless an interval or a number of
+/// samples is given.
+fn generate_sample_points(
+    git_dir: &Path,
+    history: &History,
+    range: &CommitRange<'_>,
+    options: &SamplingOptions,
+) -> Result<Vec<SamplePoint>> {
+    if let Some(n) = options.every_n_commits {
+        return generate_commit_sample_points(git_dir, history, options, n);
+    }
+
+    let (first_dt, last_dt) = options.span(history, range)?;
+    let target_times = options.target_times(first_dt, last_dt);
+    if target_times.is_empty() {
+        return Err(GitSizeError::Validation(format!(
+            "No calendar boundary between {} and {}; use a shorter --interval",
+            first_dt.format("%Y-%m-%d"),
+            last_dt.format("%Y-%m-%d")
+        )));
+    }
+    sample_points_at(git_dir, history, target_times)
+}
+
+/// Find the sample point (latest commit, or ref tips in whole-repository mode)
+/// as of each of the `target_times`.
+///
+/// The result is sorted by date, with a single sample point per date.
+fn sample_points_at(
+    git_dir: &Path,
+    history: &History,
+    mut target_times: Vec<DateTime<Utc>>,
+) -> Result<Vec<SamplePoint>> {
+    // Sort target times DESCENDING because git rev-list is descending
+    target_times.sort_by_key(|t| Reverse(t.timestamp()));
+
+    let mut sample_points = Vec::new();
+
+    // Pre-format target dates and timestamps to avoid redundant work in the loop
+    let target_dates: Vec<String> = target_times
+        .iter()
+        .map(|t| t.format("%Y-%m-%d").to_string())
+        .collect();
+    let target_timestamps: Vec<i64> = target_times.iter().map(|t| t.timestamp()).collect();
+
+    if history.whole_repository {
+        let tips_at = find_tips_at(git_dir, history, &target_timestamps)?;
+        for ((date, mut tips), &target) in target_dates
+            .into_iter()
+            .zip(tips_at)
+            .zip(&target_timestamps)
+        {
+            // Newest tip first: it is reported as the sample's commit
+            tips.sort_by_key(|(ts, _)| Reverse(*ts));
+            let mut tips = tips.into_iter();
+            if let Some((commit_time, commit_hash)) = tips.next() {
+                sample_points.push(SamplePoint {
+                    date,
+                    commit_hash,
+                    commit_time,
+                    extra_tips: tips
+                        .map(|(_, hash)| hash)
+                        .chain(history.tags_at(target))
+                        .collect(),
+                    label: None,
+                    commit_index: None,
+                });
+            }
+        }
+        sample_points.sort_by(|a, b| a.date.cmp(&b.date));
+        sample_points.dedup_by(|a, b| a.date == b.date);
+        return Ok(sample_points);
+    }
+
+    // Timestamps are not monotonic in clock-skewed histories (commits dated
+    // before their parents, common in converted SVN/CVS repositories), so
+    // commits are read with their dates capped at the dates of their children,
+    // then sorted and searched. --date-order lists children before parents, and
+    // newest first: until a skewed commit is seen, the walk can stop once the
+    // oldest target is passed.
+    // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
+    let mut child = git_command(git_dir)
+        .args(["rev-list", "--date-order"])
+        .args(history.timestamp_args(true))
+        .args(history.rev_list_args())
+        .arg("--")
+        .stdout(Stdio::piped())
+        .spawn_with_input(history.tips_input())
+        .map_err(|e| GitSizeError::Command(format!("Failed to spawn git rev-list: {}", e)))?;
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| GitSizeError::Command("Failed to open git rev-list stdout".to_string()))?;
+    let mut reader = BufReader::new(stdout);
+
+    let oldest_target = target_timestamps.iter().copied().min();
+    // (effective date, id) of the commits read, in walk order
+    let mut commits: Vec<(i64, git2::Oid)> = Vec::new();
+    // Oldest child date of the commits not read yet (the walk frontier)
+    let mut oldest_child: HashMap<git2::Oid, i64> = HashMap::new();
+    let mut skewed = 0u64;
+    let mut stopped = false;
+
+    let mut line = String::with_capacity(128);
+    while reader.read_line(&mut line)? > 0 {
+        // Commit lines are "<timestamp> <hash> <parents>..."
+        let mut parts = line.split_ascii_whitespace();
+        let ts = parts.next().and_then(|s| s.parse::<i64>().ok());
+        let oid = parts.next().and_then(|s| git2::Oid::from_str(s).ok());
+        if let (Some(mut ts), Some(oid)) = (ts, oid) {
+            if let Some(child_ts) = oldest_child.remove(&oid) {
+                if ts > child_ts {
+                    skewed += 1;
+                    ts = child_ts;
+                }
+            }
+            // --parents lists every parent, even with --first-parent
+            let parents = if history.first_parent { 1 } else { usize::MAX };
+            for parent in parts.take(parents) {
+                if let Ok(parent) = git2::Oid::from_str(parent) {
+                    let entry = oldest_child.entry(parent).or_insert(ts);
+                    *entry = (*entry).min(ts);
+                }
+            }
+            commits.push((ts, oid));
+
+            // Without skew so far, no later commit is newer than this one
+            if skewed == 0 && oldest_target.is_some_and(|oldest| ts <= oldest) {
+                let _ = child.kill();
+                stopped = true;
+                break;
+            }
+        }
+        line.clear();
+    }
+
+    let status = child.wait()?;
+    if !stopped && !status.success() {
+        return Err(GitSizeError::Command(
+            "Failed to walk repository history".to_string(),
+        ));
+    }
+    warn_clock_skew(skewed);
+
+    // Newest first; the stable sort keeps children before parents on equal dates
+    commits.sort_by_key(|(ts, _)| Reverse(*ts));
+    for (date, &target) in target_dates.into_iter().zip(&target_timestamps) {
+        // The latest commit at or before the target. It is never a capped commit,
+        // which comes after the child it takes its date from.
+        let index = commits.partition_point(|(ts, _)| *ts > target);
+        if let Some((ts, oid)) = commits.get(index) {
+            sample_points.push(SamplePoint {
+                date,
+                commit_hash: oid.to_string(),
+                commit_time: *ts,
+                extra_tips: Vec::new(),
+                label: None,
+                commit_index: None,
+            });
+        }
+    }
+
+    // Sort by date ascending for the rest of the application
+    sample_points.sort_by(|a, b| a.date.cmp(&b.date));
+    sample_points.dedup_by(|a, b| a.date == b.date);
+
+    Ok(sample_points)
+}
+
+/// Generate a sample point at every `n`-th commit of the analyzed history.
+///
+/// Commits are numbered from the oldest one in `git rev-list --reverse` order,
+/// and sample points are taken at commits `n`, `2n`, ... plus the last commit,
+/// so that busy periods get more sample points than quiet ones. In
+/// whole-repository mode, each sample point measures the refs as they stood on
+/// the date of its commit. Commits outside the `since`/`until` bounds are
+/// skipped but still counted.
+fn generate_commit_sample_points(
+    git_dir: &Path,
+    history: &History,
+    options: &SamplingOptions,
+    n: u32,
+) -> Result<Vec<SamplePoint>> {
+    // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
+    let mut child = git_command(git_dir)
+        .args(["rev-list", "--reverse"])
+        .args(history.timestamp_args(false))
+        .args(history.rev_list_args())
+        .arg("--")
+        .stdout(Stdio::piped())
+        .spawn_with_input(history.tips_input())
+        .map_err(|e| GitSizeError::Command(format!("Failed to spawn git rev-list: {}", e)))?;
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| GitSizeError::Command("Failed to open git rev-list stdout".to_string()))?;
+    let mut reader = BufReader::new(stdout);
+
+    let in_range = |ts: i64| {
+        options.since.map_or(true, |since| ts >= since)
+            && options.until.map_or(true, |until| ts <= until)
+    };
+
+    // (commit index, timestamp, hash)
+    let mut selected: Vec<(u64, i64, String)> = Vec::new();
+    // Last commit within the date range, sampled even if not at a multiple of n
+    let mut last: Option<(u64, i64, String)> = None;
+    let mut index = 0u64;
+    let mut line = String::with_capacity(64);
+    while reader.read_line(&mut line)? > 0 {
+        let mut parts = line.split_ascii_whitespace();
+        let ts = parts.next().and_then(|s| s.parse::<i64>().ok());
+        let hash = parts.next();
+        if let (Some(ts), Some(hash)) = (ts, hash) {
+            index += 1;
+            if in_range(ts) {
+                if index % u64::from(n) == 0 {
+                    selected.push((index, ts, hash.to_string()));
+                    last = None;
+                } else {
+                    last = Some((index, ts, hash.to_string()));
+                }
+            }
+        }
+        line.clear();
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(GitSizeError::Command(
+            "Failed to walk repository history".to_string(),
+        ));
+    }
+    selected.extend(last);
+
+    if selected.is_empty() {
+        return Err(GitSizeError::Validation(
+            "No commits in the selected date range".to_string(),
+        ));
+    }
+
+    // In whole-repository mode, measure every ref tip as of each commit's date
+    let tips_at = if history.whole_repository {
+        let mut timestamps: Vec<i64> = selected.iter().map(|(_, ts, _)| *ts).collect();
+        timestamps.sort_by_key(|&ts| Reverse(ts));
+        timestamps.dedup();
+        let tips_at = find_tips_at(git_dir, history, &timestamps)?;
+        timestamps.into_iter().zip(tips_at).collect()
+    } else {
+        HashMap::new()
+    };
+
+    selected
+        .into_iter()
+        .map(|(index, ts, commit_hash)| {
+            let date = DateTime::from_timestamp(ts, 0)
+                .ok_or_else(|| {
+                    GitSizeError::Validation(format!("Invalid commit timestamp: {}", ts))
+                })?
+                .format("%Y-%m-%d")
+                .to_string();
+            let extra_tips = tips_at
+                .get(&ts)
+                .map(|tips: &Vec<(i64, String)>| {
+                    tips.iter()
+                        .map(|(_, hash)| hash.clone())
+                        .filter(|hash| *hash != commit_hash)
+                        .chain(history.tags_at(ts))
+                        .collect()
+                })
+                .unwrap_or_default();
+            Ok(SamplePoint {
+                date,
+                commit_hash,
+                commit_time: ts,
+                extra_tips,
+                label: None,
+                commit_index: Some(index),
+            })
+        })
+        .collect()
+}
+
+/// Measure the sizes selected by the command-line options at a sample point.
+///
+/// Sizes found in the `journal` of an interrupted run or in the `cache` are
+/// reused, and new measurements are added to both, after waiting for a slot of
+/// the memory `budget`. The added size, which depends on the previous sample
+/// point, is left unset.
+fn measure_sample_point(
+    git_dir: &Path,
+    sample: &SamplePoint,
+    args: &Args,
+    cache: Option<&MeasurementCache>,
+    journal: Option<&Journal>,
+    budget: Option<&MemoryBudget>,
+) -> Result<SizeMeasurement> {
+    check_interrupted()?;
+    let with_stats = args.by_type || args.counts;
+    let journaled = journal.and_then(|journal| journal.get(sample, args.uncompressed, with_stats));
+    let cached = || cache.and_then(|cache| cache.get(sample, args.uncompressed, with_stats));
+    let sizes = match journaled.or_else(cached) {
+        Some(sizes) => sizes,
+        None => {
+            let _permit = budget.map(MemoryBudget::acquire);
+            let sizes = measure_sizes(git_dir, sample, args)?;
+            if let Some(cache) = cache {
+                cache.insert(sample, sizes);
+            }
+            if let Some(journal) = journal {
+                journal.record(sample, sizes)?;
+            }
+            sizes
+        }
+    };
+
+    Ok(SizeMeasurement {
+        date: sample.date.clone(),
+        commit_hash: sample.commit_hash.clone(),
+        commit_time: sample.commit_time,
+        label: sample.label.clone(),
+        commit_index: sample.commit_index,
+        cumulative_size: sizes.cumulative_size,
+        // Cached entries may hold more than was asked for
+        uncompressed_size: sizes.uncompressed_size.filter(|_| args.uncompressed),
+        object_stats: sizes.object_stats.filter(|_| with_stats),
+        // Measured once all sample points are known
+        added_size: None,
+    })
+}
+
+/// Run the size measurements selected by the command-line options at a sample point.
+fn measure_sizes(git_dir: &Path, sample: &SamplePoint, args: &Args) -> Result<CachedSizes> {
+    let (packed_size, uncompressed_size) = measure_size_at_commit(
+        git_dir,
+        &sample.commit_hash,
+        &sample.extra_tips,
+        args.debug,
+        args.uncompressed,
+    )?;
+
+    let object_stats = if args.by_type || args.counts {
+        let sizes = measure_object_stats(git_dir, &sample.commit_hash, &sample.extra_tips)?;
+        if args.debug {
+            println!(
+                "  Packed by type: commits {}, trees {}, blobs {}, tags {}",
+                format_size(sizes.packed.commits),
+                format_size(sizes.packed.trees),
+                format_size(sizes.packed.blobs),
+                format_size(sizes.packed.tags)
+            );
+            println!(
+                "  Counts: commits {}, trees {}, blobs {}, tags {}",
+                sizes.counts.commits, sizes.counts.trees, sizes.counts.blobs, sizes.counts.tags
+            );
+        }
+        Some(sizes)
+    } else {
+        None
+    };
+
+    Ok(CachedSizes {
+        cumulative_size: packed_size,
+        uncompressed_size,
+        object_stats,
+    })
+}
+
+/// Sizes of a sample point, as stored in the measurement cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CachedSizes {
+    /// Cumulative packed size in bytes
+    cumulative_size: u64,
+    /// Uncompressed blob size in bytes (if calculated)
+    uncompressed_size: Option<u64>,
+    /// Sizes and counts per object type (if calculated)
+    object_stats: Option<ObjectStats>,
+}
+
+impl CachedSizes {
+    /// Whether these sizes include the uncompressed size and object statistics, if asked for
+    fn covers(&self, uncompressed: bool, stats: bool) -> bool {
+        (!uncompressed || self.uncompressed_size.is_some())
+            && (!stats || self.object_stats.is_some())
+    }
+
+    /// Cache file record: key, packed size, uncompressed size, then object statistics
+    fn record(&self, key: &str) -> Vec<String> {
+        let mut record = vec![
+            key.to_string(),
+            self.cumulative_size.to_string(),
+            self.uncompressed_size
+                .map_or_else(String::new, |size| size.to_string()),
+        ];
+        match self.object_stats {
+            Some(stats) => record.extend(
+                [stats.packed, stats.uncompressed, stats.counts]
+                    .iter()
+                    .flat_map(|totals| totals.values())
+                    .map(|value| value.to_string()),
+            ),
+            None => record.extend(std::iter::repeat(String::new()).take(12)),
+        }
+        record
+    }
+
+    /// Parse a cache file record written by `record()`.
+    fn from_record(record: &csv::StringRecord) -> Option<(String, CachedSizes)> {
+        let optional = |index: usize| -> Option<Option<u64>> {
+            match record.get(index)? {
+                "" => Some(None),
+                value => value.parse().ok().map(Some),
+            }
+        };
+        let totals = |first: usize| -> Option<Option<TypeTotals>> {
+            let values = [
+                optional(first)?,
+                optional(first + 1)?,
+                optional(first + 2)?,
+                optional(first + 3)?,
+            ];
+            Some(match values {
+                [Some(commits), Some(trees), Some(blobs), Some(tags)] => Some(TypeTotals {
+                    commits,
+                    trees,
+                    blobs,
+                    tags,
+                }),
+                _ => None,
+            })
+        };
+        let object_stats = match (totals(3)?, totals(7)?, totals(11)?) {
+            (Some(packed), Some(uncompressed), Some(counts)) => Some(ObjectStats {
+                packed,
+                uncompressed,
+                counts,
+            }),
+            _ => None,
+        };
+        let sizes = CachedSizes {
+            cumulative_size: record.get(1)?.parse().ok()?,
+            uncompressed_size: optional(2)?,
+            object_stats,
+        };
+        Some((record.get(0)?.to_string(), sizes))
+    }
+}
+
+/// On-disk cache of sample point measurements, so that re-runs only measure new
+/// sample points.
+///
+/// Packed sizes only change when objects move to other files, so measurements
+/// are tied to the pack files they were made with: `<fingerprint>.state` lists
+/// the pack files, and `<fingerprint>.csv` holds the measurements. They stay
+/// valid as long as all those packs exist, which fetches preserve (they only
+/// add packs), while `git gc` and `git repack` remove them.
+///
+/// Measurements whose history has a loose commit count loose objects, whose
+/// size changes once they are packed, so they are not stored until then; other
+/// measurements are stored whatever loose objects the repository has. `save()` merges all valid measurements into a single pair of files for the
+/// current packs, so the cache never holds more than one state.
+struct MeasurementCache {
+    /// Cache directory
+    dir: PathBuf,
+    /// Fingerprint of the current pack files
+    fingerprint: String,
+    /// Current pack files, sorted
+    packs: Vec<String>,
+    /// Loose commits and tags, which the stored measurements must not reach
+    loose_objects: Vec<git2::Oid>,
+    /// Valid measurements, keyed by commit and extra tips
+    entries: HashMap<String, CachedSizes>,
+    /// Earlier states whose measurements are merged into the current one by `save()`
+    merged_states: Vec<PathBuf>,
+    /// Measurements made during this run with the tips they were made from,
+    /// written by `save()`
+    new_entries: Mutex<Vec<(String, Vec<String>, CachedSizes)>>,
+    /// Number of measurements reused
+    hits: AtomicUsize,
+}
+
+impl MeasurementCache {
+    /// Open the cache in `dir` for the repository, loading the measurements that
+    /// are still valid and removing the stale ones.
+    fn open(dir: &Path, repo: &Repository) -> Result<MeasurementCache> {
+        std::fs::create_dir_all(dir)?;
+
+        let packs = pack_files(repo);
+        let fingerprint = format!("{:016x}", fnv1a(packs.join("\n").as_bytes()));
+
+        let mut entries: HashMap<String, CachedSizes> = HashMap::new();
+        let mut merged_states = Vec::new();
+        for entry in std::fs::read_dir(dir)?.flatten() {
+            let path = entry.path();
+            if path.extension().map_or(true, |ext| ext != "state") {
+                continue;
+            }
+            let measurements = path.with_extension("csv");
+            let state = std::fs::read_to_string(&path)?;
+            if !state
+                .lines()
+                .all(|file| packs.binary_search_by(|f| f.as_str().cmp(file)).is_ok())
+            {
+                // Repacked since: these sizes are outdated
+                let _ = std::fs::remove_file(&measurements);
+                std::fs::remove_file(&path)?;
+                continue;
+            }
+            let Ok(mut reader) = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_path(&measurements)
+            else {
+                continue;
+            };
+            for record in reader.records().map_while(|record| record.ok()) {
+                let Some((key, sizes)) = CachedSizes::from_record(&record) else {
+                    continue;
+                };
+                // Measurements of the same sample point may hold different sizes
+                let known = entries.entry(key).or_insert(sizes);
+                known.uncompressed_size = known.uncompressed_size.or(sizes.uncompressed_size);
+                known.object_stats = known.object_stats.or(sizes.object_stats);
+            }
+            if path.file_stem() != Some(OsStr::new(&fingerprint)) {
+                merged_states.push(path);
+            }
+        }
+
+        Ok(MeasurementCache {
+            dir: dir.to_path_buf(),
+            fingerprint,
+            packs,
+            loose_objects: loose_objects(repo),
+            entries,
+            merged_states,
+            new_entries: Mutex::new(Vec::new()),
+            hits: AtomicUsize::new(0),
+        })
+    }
+
+    /// Cache key of a sample point: its commit, then its other ref tips in order
+    fn key(sample: &SamplePoint) -> String {
+        let mut tips: Vec<&str> = sample.extra_tips.iter().map(String::as_str).collect();
+        tips.sort_unstable();
+        std::iter::once(sample.commit_hash.as_str())
+            .chain(tips)
+            .collect::<Vec<_>>()
+            .join("+")
+    }
+
+    /// Cached sizes of a sample point, if they include what is asked for.
+    fn get(&self, sample: &SamplePoint, uncompressed: bool, stats: bool) -> Option<CachedSizes> {
+        let sizes = self
+            .entries
+            .get(&Self::key(sample))
+            .filter(|sizes| sizes.covers(uncompressed, stats))?;
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(*sizes)
+    }
+
+    /// Record the sizes measured at a sample point, to be written by `save()`.
+    fn insert(&self, sample: &SamplePoint, sizes: CachedSizes) {
+        if let Ok(mut new_entries) = self.new_entries.lock() {
+            let tips = std::iter::once(&sample.commit_hash)
+                .chain(&sample.extra_tips)
+                .cloned()
+                .collect();
+            new_entries.push((Self::key(sample), tips, sizes));
+        }
+    }
+
+    /// Number of sample points whose sizes came from the cache
+    fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Whether a loose commit or tag is reachable from `tip`.
+    fn reaches_loose_object(&self, repo: &Repository, tip: &str) -> bool {
+        if self.loose_objects.is_empty() {
+            return false;
+        }
+        let Ok((object, commit)) = git2::Oid::from_str(tip)
+            .and_then(|oid| repo.find_object(oid, None))
+            .and_then(|object| Ok((object.id(), object.peel_to_commit()?.id())))
+        else {
+            return true;
+        };
+        self.loose_objects.iter().any(|&loose| {
+            object == loose
+                || commit == loose
+                || repo.graph_descendant_of(commit, loose).unwrap_or(true)
+        })
+    }
+
+    /// Store the valid measurements, including those made during this run
+    /// that reach no loose commit, as the single state of the current pack files.
+    fn save(&self, repo: &Repository) -> Result<()> {
+        let new_entries = self
+            .new_entries
+            .lock()
+            .map_err(|_| GitSizeError::Validation("Measurement cache is poisoned".to_string()))?;
+        // Tips are shared by many sample points in whole-repository mode
+        let mut reaches_loose: HashMap<&str, bool> = HashMap::new();
+        let new_entries: Vec<(&String, &CachedSizes)> = new_entries
+            .iter()
+            .filter(|(_, tips, _)| {
+                !tips.iter().any(|tip| {
+                    *reaches_loose
+                        .entry(tip)
+                        .or_insert_with(|| self.reaches_loose_object(repo, tip))
+                })
+            })
+            .map(|(key, _, sizes)| (key, sizes))
+            .collect();
+        if new_entries.is_empty() && self.merged_states.is_empty() {
+            return Ok(());
+        }
+
+        let mut entries = self.entries.clone();
+        for (key, sizes) in new_entries {
+            let known = entries.entry(key.clone()).or_insert(*sizes);
+            known.uncompressed_size = sizes.uncompressed_size.or(known.uncompressed_size);
+            known.object_stats = sizes.object_stats.or(known.object_stats);
+        }
+        let mut keys: Vec<&String> = entries.keys().collect();
+        keys.sort_unstable();
+
+        // The measurements are in place before the state that validates them
+        write_csv_atomically(&self.dir.join(format!("{}.csv", self.fingerprint)), |wtr| {
+            for key in keys {
+                wtr.write_record(entries[key].record(key))?;
+            }
+            Ok(())
+        })?;
+        let content: String = self
+            .packs
+            .iter()
+            .map(|file| format!("{}\n", file))
+            .collect();
+        std::fs::write(
+            self.dir.join(format!("{}.state", self.fingerprint)),
+            content,
+        )?;
+        for state in &self.merged_states {
+            std::fs::remove_file(state)?;
+            let _ = std::fs::remove_file(state.with_extension("csv"));
+        }
+        Ok(())
+    }
+}
+
+/// Checkpoint journal of the measurements of a run (`<output>.journal`), so that
+/// an interrupted run can be resumed with `--resume`.
+///
+/// Measurements are appended and flushed one by one as they complete, in the
+/// record format of the measurement cache, keyed by sample date and commit.
+/// Added sizes (`--added`) are two-field records: their key, which also names
+/// the previous sample point, and the size. The file is only created once a
+/// measurement is made, and a run that fails with an error (rather than being
+/// interrupted or killed) ends it with a `failed` marker record.
+struct Journal {
+    /// Journal file
+    path: PathBuf,
+    /// Measurements of the interrupted run, keyed by sample date and commit
+    entries: HashMap<String, CachedSizes>,
+    /// Added sizes measured by the interrupted run, keyed by `added_key()`
+    added: HashMap<String, u64>,
+    /// Length of the journaled records kept by `--resume`, after which writes go
+    kept_len: u64,
+    /// Writer appending to the journal file, once the first record is written
+    writer: Mutex<Option<Writer<std::fs::File>>>,
+    /// Number of measurements reused
+    hits: AtomicUsize,
+}
+
+/// Record ending the journal of a run that failed with an error
+const JOURNAL_FAILED_MARKER: &str = "failed";
+
+impl Journal {
+    /// Open the journal of the `output` CSV. With `resume`, the measurements it
+    /// holds are read and kept. Otherwise it is started afresh, unless it holds
+    /// the measurements of an interrupted or killed run, which a plain rerun must
+    /// not discard; the journal of a failed run is discarded with a warning.
+    fn open(output: &Path, resume: bool) -> Result<Journal> {
+        let mut path = output.as_os_str().to_owned();
+        path.push(".journal");
+        let path = PathBuf::from(path);
+
+        let mut entries = HashMap::new();
+        let mut added = HashMap::new();
+        let mut kept_len = 0;
+        if path.exists() {
+            let content = std::fs::read(&path)?;
+            // A run killed while writing may leave a partial last line
+            let complete_len = content
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or(0, |newline| newline + 1);
+            let mut failed = false;
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(&content[..complete_len]);
+            for record in reader.records().map_while(|record| record.ok()) {
+                // Only a marker ending the journal tells that the last run failed
+                failed = record.len() == 1 && record.get(0) == Some(JOURNAL_FAILED_MARKER);
+                if record.len() == 2 {
+                    if let (Some(key), Some(Ok(size))) =
+                        (record.get(0), record.get(1).map(str::parse::<u64>))
+                    {
+                        added.insert(key.to_string(), size);
+                    }
+                } else if let Some((key, sizes)) = CachedSizes::from_record(&record) {
+                    entries.insert(key, sizes);
+                }
+            }
+
+            if resume {
+                kept_len = complete_len as u64;
+            } else if complete_len > 0 && !failed {
+                return Err(GitSizeError::Validation(format!(
+                    "{} holds the measurements of an interrupted run: rerun with --resume \
+                     to reuse them, or delete it to start over",
+                    path.display()
+                )));
+            } else {
+                if failed {
+                    eprintln!(
+                        "⚠️  Warning: Discarding {}, left by a run that failed",
+                        path.display()
+                    );
+                }
+                entries.clear();
+                added.clear();
+                std::fs::remove_file(&path)?;
+            }
+        }
+
+        Ok(Journal {
+            path,
+            entries,
+            added,
+            kept_len,
+            writer: Mutex::new(None),
+            hits: AtomicUsize::new(0),
+        })
+    }
+
+    /// Append a record to the journal, creating it on the first write.
+    fn write<I, T>(&self, record: I) -> Result<()>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| GitSizeError::Validation("Checkpoint journal is poisoned".to_string()))?;
+        let writer = match &mut *writer {
+            Some(writer) => writer,
+            None => {
+                let mut file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .truncate(false)
+                    .open(&self.path)?;
+                // Writes go after the kept measurements
+                file.set_len(self.kept_len)?;
+                file.seek(SeekFrom::End(0))?;
+                writer.insert(csv::WriterBuilder::new().flexible(true).from_writer(file))
+            }
+        };
+        writer.write_record(record)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Journal key of a sample point: its date and measurement cache key
+    fn key(sample: &SamplePoint) -> String {
+        format!("{} {}", sample.date, MeasurementCache::key(sample))
+    }
+
+    /// Sizes of a sample point measured by the interrupted run, if they include
+    /// what is asked for.
+    fn get(&self, sample: &SamplePoint, uncompressed: bool, stats: bool) -> Option<CachedSizes> {
+        let sizes = self
+            .entries
+            .get(&Self::key(sample))
+            .filter(|sizes| sizes.covers(uncompressed, stats))?;
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(*sizes)
+    }
+
+    /// Append the sizes measured at a sample point to the journal.
+    fn record(&self, sample: &SamplePoint, sizes: CachedSizes) -> Result<()> {
+        self.write(sizes.record(&Self::key(sample)))
+    }
+
+    /// Journal key of the size added at a sample point since `previous`
+    fn added_key(sample: &SamplePoint, previous: Option<&SamplePoint>) -> String {
+        format!(
+            "{} ^{}",
+            Self::key(sample),
+            previous.map(MeasurementCache::key).unwrap_or_default()
+        )
+    }
+
+    /// Size added at a sample point since `previous`, measured by the interrupted run
+    fn get_added(&self, sample: &SamplePoint, previous: Option<&SamplePoint>) -> Option<u64> {
+        self.added.get(&Self::added_key(sample, previous)).copied()
+    }
+
+    /// Append the size added at a sample point since `previous` to the journal.
+    fn record_added(
+        &self,
+        sample: &SamplePoint,
+        previous: Option<&SamplePoint>,
+        size: u64,
+    ) -> Result<()> {
+        self.write([Self::added_key(sample, previous), size.to_string()])
+    }
+
+    /// Mark the journal, if any, as left by a run that failed with `error`
+    /// rather than being interrupted, and return the error.
+    fn fail(&self, error: GitSizeError) -> GitSizeError {
+        let written = self.writer.lock().is_ok_and(|writer| writer.is_some());
+        if written && !interrupted() {
+            // The run fails with its own error whether or not the marker is written
+            let _ = self.write([JOURNAL_FAILED_MARKER]);
+        }
+        error
+    }
+
+    /// Tell how to resume from the measurements journaled before an interruption.
+    fn report_interrupted(&self) {
+        if self.path.exists() {
+            eprintln!(
+                "Measurements so far are kept in {}, rerun with --resume to continue",
+                self.path.display()
+            );
+        }
+    }
+
+    /// Number of sample points whose sizes came from the journal
+    fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Remove the journal, if any, once the run has completed.
+    fn remove(self) -> Result<()> {
+        drop(self.writer);
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => Ok(result?),
+        }
+    }
+}
+
+/// Memory used by `git rev-list --objects` per object walked (object structs and lookup table)
+const WALK_MEMORY_PER_OBJECT: u64 = 160;
+/// Memory used on top of the walk by measurements reading objects with `git cat-file`
+/// (mostly its delta base cache, 96 MiB by default)
+const CAT_FILE_MEMORY: u64 = 96 << 20;
+
+/// Limit on the number of measurements running at once (`--max-memory`)
+///
+/// Each measurement walks up to every object of the repository, so its memory
+/// use is estimated from the object count. Measurements wait for a slot before
+/// starting their git processes.
+struct MemoryBudget {
+    /// Number of measurements that fit in the budget
+    slots: usize,
+    /// Estimated memory used by a measurement
+    per_measurement: u64,
+    /// Slots not taken by a running measurement
+    available: Mutex<usize>,
+    /// Signaled when a slot is released
+    released: Condvar,
+}
+
+impl MemoryBudget {
+    /// Budget `max_memory` bytes for measurements walking `objects` objects.
+    /// `cat_file` measurements also read the objects (uncompressed sizes,
+    /// object types, breakdowns).
+    fn new(max_memory: u64, objects: u64, cat_file: bool) -> MemoryBudget {
+        let walk = objects.saturating_mul(WALK_MEMORY_PER_OBJECT);
+        let per_measurement = if cat_file {
+            walk.saturating_add(CAT_FILE_MEMORY)
+        } else {
+            walk
+        };
+        // A single measurement runs even if it does not fit
+        let slots = usize::try_from(max_memory / per_measurement.max(1))
+            .unwrap_or(usize::MAX)
+            .max(1);
+        MemoryBudget {
+            slots,
+            per_measurement,
+            available: Mutex::new(slots),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Wait for a slot, held until the returned permit is dropped.
+    fn acquire(&self) -> MemoryPermit<'_> {
+        let mut available = self
+            .available
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        while *available == 0 {
+            available = self
+                .released
+                .wait(available)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        *available -= 1;
+        MemoryPermit { budget: self }
+    }
+}
+
+/// A slot of the memory budget, released when dropped
+struct MemoryPermit<'a> {
+    budget: &'a MemoryBudget,
+}
+
+impl Drop for MemoryPermit<'_> {
+    fn drop(&mut self) {
+        let mut available = self
+            .budget
+            .available
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        *available += 1;
+        self.budget.released.notify_one();
+    }
+}
+
+/// Number of objects in the repository, loose and packed (`git count-objects -v`).
+fn count_objects(git_dir: &Path) -> Result<u64> {
+    let output = git_command(git_dir)
+        .args(["count-objects", "-v"])
+        .output_tracked()
+        .map_err(|e| GitSizeError::Command(format!("Failed to run git count-objects: {}", e)))?;
+    if !output.status.success() {
+        return Err(GitSizeError::Command(format!(
+            "git count-objects failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    // "count: <loose objects>" and "in-pack: <packed objects>" lines
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            line.strip_prefix("count: ")
+                .or_else(|| line.strip_prefix("in-pack: "))
+        })
+        .filter_map(|count| count.trim().parse::<u64>().ok())
+        .sum())
+}
+
+/// Pack files of the repository, sorted by name.
+fn pack_files(repo: &Repository) -> Vec<String> {
+    let mut files = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(repo.path().join("objects/pack")) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "pack") {
+                if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                    files.push(name.to_string());
+                }
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Loose commits and tags of the repository.
+fn loose_objects(repo: &Repository) -> Vec<git2::Oid> {
+    let Ok(odb) = repo.odb() else {
+        return Vec::new();
+    };
+    let Ok(dirs) = std::fs::read_dir(repo.path().join("objects")) else {
+        return Vec::new();
+    };
+    // Loose objects live in two-hex-digit fan-out directories, named after the
+    // rest of their id
+    let mut objects = Vec::new();
+    for dir in dirs.flatten() {
+        let Some(prefix) = dir.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if prefix.len() != 2 || !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+            continue;
+        }
+        let Ok(files) = std::fs::read_dir(dir.path()) else {
+            continue;
+        };
+        for file in files.flatten() {
+            let Some(oid) = file
+                .file_name()
+                .to_str()
+                .and_then(|rest| git2::Oid::from_str(&format!("{}{}", prefix, rest)).ok())
+            else {
+                continue;
+            };
+            if odb.read_header(oid).is_ok_and(|(_, kind)| {
+                matches!(kind, git2::ObjectType::Commit | git2::ObjectType::Tag)
+            }) {
+                objects.push(oid);
+            }
+        }
+    }
+    objects
+}
+
+/// 64-bit FNV-1a hash, used to name cache files after the pack files.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Adaptively add sample points where the size changes the most.
+///
+/// Starting from the measured `samples`, every interval whose size change
+/// exceeds the threshold is split at its midpoint, and the new sample points
+/// are measured. This is repeated until no interval exceeds the threshold,
+/// all such intervals are shorter than twice the minimum interval, or the
+/// sample budget is spent (largest changes first). New sample points are
+/// measured with `measure`. Returns the number of sample points added.
+fn refine_samples<F>(
+    git_dir: &Path,
+    history: &History,
+    refinement: &Refinement,
+    measure: F,
+    samples: &mut Vec<SamplePoint>,
+    results: &mut Vec<SizeMeasurement>,
+    pb: &ProgressBar,
+) -> Result<usize>
+where
+    F: Fn(&SamplePoint) -> Result<SizeMeasurement> + Sync,
+{
+    let initial_count = samples.len();
+
+    loop {
+        check_interrupted()?;
+        let budget = refinement.max_samples.saturating_sub(samples.len());
+        if budget == 0 {
+            break;
+        }
+
+        // (size change, midpoint) of the intervals to split
+        let mut splits: Vec<(u64, DateTime<Utc>)> = results
+            .windows(2)
+            .filter_map(|pair| {
+                let change = pair[1].cumulative_size.abs_diff(pair[0].cumulative_size);
+                if !refinement
+                    .threshold
+                    .exceeded(pair[0].cumulative_size, change)
+                {
+                    return None;
+                }
+                let start = date_time(&pair[0].date)?;
+                let end = date_time(&pair[1].date)?;
+                let shortest_split = refinement.min_interval.nth_after(start, 2)?;
+                (shortest_split <= end).then(|| (change, start + (end - start) / 2))
+            })
+            .collect();
+        if splits.is_empty() {
+            break;
+        }
+        splits.sort_by_key(|(change, _)| Reverse(*change));
+        splits.truncate(budget);
+
+        let new_samples = sample_points_at(
+            git_dir,
+            history,
+            splits.into_iter().map(|(_, t)| t).collect(),
+        )?;
+        pb.inc_length(new_samples.len() as u64);
+        let new_results = new_samples
+            .par_iter()
+            .map(|sample| {
+                let measurement = measure(sample)?;
+                pb.inc(1);
+                Ok(measurement)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Merge the new sample points in, keeping a single one per date
+        let count = samples.len();
+        let mut merged: Vec<(SamplePoint, SizeMeasurement)> = samples
+            .drain(..)
+            .zip(results.drain(..))
+            .chain(new_samples.into_iter().zip(new_results))
+            .collect();
+        merged.sort_by(|a, b| a.0.date.cmp(&b.0.date));
+        merged.dedup_by(|a, b| a.0.date == b.0.date);
+        let (merged_samples, merged_results) = merged.into_iter().unzip();
+        *samples = merged_samples;
+        *results = merged_results;
+
+        if samples.len() == count {
+            // Nothing new could be sampled
+            break;
+        }
+    }
+
+    Ok(samples.len() - initial_count)
+}
+
+/// Midnight UTC of a sample date (YYYY-MM-DD).
+fn date_time(date: &str) -> Option<DateTime<Utc>> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()?
+        .and_hms_opt(0, 0, 0)
+        .map(|naive| naive.and_utc())
+}
+
+/// Generate one sample point per tag reachable from the analyzed history.
+///
+/// Each matching tag becomes a sample point measuring everything reachable from
+/// the tagged commit, dated by the tag (tagger date of annotated tags, commit
+/// date of lightweight ones) and labeled with the tag name. Tags outside the
+/// `since`/`until` bounds are skipped. Sample points are in date order whatever
+/// `tags.sort`, and unlike date-based sampling, several may share a date.
+fn generate_tag_sample_points(
+    repo: &Repository,
+    history: &History,
+    options: &SamplingOptions,
+    tags: &TagSampling,
+) -> Result<Vec<SamplePoint>> {
+    // A pattern is matched against the tag name, like `git tag --list <pattern>`
+    let pattern = match &tags.pattern {
+        Some(pattern) => format!("refs/tags/{}", pattern),
+        None => "refs/tags".to_string(),
+    };
+
+    // Let git select the tags reachable from a single tip: this uses the
+    // commit-graph and reachability bitmaps when available. The tips of
+    // whole-repository mode may not fit on a command line, so the commits
+    // reachable from them are listed instead.
+    let mut command = git_command(repo.path());
+    command.args(["for-each-ref", "--format=%(refname)"]);
+    let reachable = match history.tips.as_slice() {
+        [tip] => {
+            command.arg(format!("--merged={}", tip));
+            None
+        }
+        _ => Some(reachable_commits(repo.path(), history)?),
+    };
+    let output = command
+        .arg(&pattern)
+        .output_tracked()
+        .map_err(|e| GitSizeError::Command(format!("Failed to run git for-each-ref: {}", e)))?;
+    if !output.status.success() {
+        return Err(GitSizeError::Command(format!(
+            "git for-each-ref failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let mut tagged = Vec::new();
+    for refname in String::from_utf8_lossy(&output.stdout).lines() {
+        let reference = repo.find_reference(refname)?;
+        // Tags of trees or blobs have no place in a history
+        let Ok(commit) = reference.peel_to_commit() else {
+            continue;
+        };
+        if reachable
+            .as_ref()
+            .is_some_and(|reachable| !reachable.contains(&commit.id().to_string()))
+        {
+            continue;
+        }
+        // Annotated tags are measured with their tag object
+        let tag = reference.target().and_then(|oid| repo.find_tag(oid).ok());
+        let time = tag
+            .as_ref()
+            .and_then(|tag| tag.tagger().map(|tagger| tagger.when().seconds()))
+            .unwrap_or_else(|| history.commit_time(&commit));
+        if options.since.is_some_and(|since| time < since)
+            || options.until.is_some_and(|until| time > until)
+        {
+            continue;
+        }
+        let name = refname.strip_prefix("refs/tags/").unwrap_or(refname);
+        tagged.push((
+            time,
+            name.to_string(),
+            commit.id().to_string(),
+            history.commit_time(&commit),
+            tag.map(|tag| tag.id().to_string()),
+        ));
+    }
+
+    if tagged.is_empty() {
+        return Err(GitSizeError::Validation(format!(
+            "No tags matching {:?} in the analyzed history",
+            pattern.strip_prefix("refs/tags/").unwrap_or("*")
+        )));
+    }
+
+    // Sample points follow the history whatever the order of the CSV rows
+    tagged.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+
+    tagged
+        .into_iter()
+        .map(|(time, name, commit_hash, commit_time, tag)| {
+            let date = DateTime::from_timestamp(time, 0)
+                .ok_or_else(|| {
+                    GitSizeError::Validation(format!("Invalid date of tag {}: {}", name, time))
+                })?
+                .format("%Y-%m-%d")
+                .to_string();
+            Ok(SamplePoint {
+                date,
+                commit_hash,
+                commit_time,
+                extra_tips: tag.into_iter().collect(),
+                label: Some(name),
+                commit_index: None,
+            })
+        })
+        .collect()
+}
+
+/// Every commit reachable from the tips of the analyzed history.
+fn reachable_commits(git_dir: &Path, history: &History) -> Result<HashSet<String>> {
+    // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
+    let output = git_command(git_dir)
+        .args(["rev-list", "--stdin", "--"])
+        .output_with_input(history.tips_input())
+        .map_err(|e| GitSizeError::Command(format!("Failed to run git rev-list: {}", e)))?;
+    if !output.status.success() {
+        return Err(GitSizeError::Command(format!(
+            "git rev-list failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim().to_string())
+        .collect())
+}
+
+/// Compare two tag names by the version number they contain.
+///
+/// An optional `v` prefix is ignored, numeric components are compared as
+/// numbers (`1.10` > `1.9`) and a pre-release (`1.0-rc1`) sorts before its
+/// release. Names without a version number sort last, by name.
+fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    // (numeric components, pre-release suffix)
+    fn parse(name: &str) -> Option<(Vec<u64>, Option<&str>)> {
+        let version = name.strip_prefix(['v', 'V']).unwrap_or(name);
+        let (numbers, pre) = match version.find(['-', '+']) {
+            // Build metadata (`+...`) does not take part in the ordering
+            Some(idx) if version[idx..].starts_with('-') => {
+                let pre = &version[idx + 1..];
+                (&version[..idx], Some(pre.split('+').next().unwrap_or(pre)))
+            }
+            Some(idx) => (&version[..idx], None),
+            None => (version, None),
+        };
+        let numbers = numbers
+            .split('.')
+            .map(|n| n.parse::<u64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        Some((numbers, pre))
+    }
+
+    match (parse(a), parse(b)) {
+        (Some((numbers_a, pre_a)), Some((numbers_b, pre_b))) => numbers_a
+            .cmp(&numbers_b)
+            .then_with(|| match (pre_a, pre_b) {
+                (None, None) => std::cmp::Ordering::Equal,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (Some(pre_a), Some(pre_b)) => pre_a.cmp(pre_b),
+            }),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    }
+    .then_with(|| a.cmp(b))
+}
+
+/// Reconstruct the set of ref tips as of each target timestamp (whole-repository mode).
+///
+/// Tips are reconstructed from commit dates with a single walk over `history`:
+/// a commit is a tip as of date D when it is dated at or before D while none of
+/// its children is. The union of objects reachable from these
+/// commits is exactly the set of objects reachable from any ref as it stood on
+/// date D (assuming commit dates never go backwards along history).
+///
+/// `target_timestamps` must be sorted descending; the result is aligned with it
+/// and holds `(timestamp, hash)` pairs.
+fn find_tips_at(
+    git_dir: &Path,
+    history: &History,
+    target_timestamps: &[i64],
+) -> Result<Vec<Vec<(i64, String)>>> {
+    let mut tips_at = vec![Vec::new(); target_timestamps.len()];
+
+    // --date-order guarantees children are listed before their parents, so the
+    // oldest child date of a commit is known by the time the commit itself is read.
+    // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
+    let mut child = git_command(git_dir)
+        .args(["rev-list", "--date-order"])
+        .args(history.timestamp_args(true))
+        .args(history.rev_list_args())
+        .arg("--")
+        .stdout(Stdio::piped())
+        .spawn_with_input(history.tips_input())
+        .map_err(|e| GitSizeError::Command(format!("Failed to spawn git rev-list: {}", e)))?;
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| GitSizeError::Command("Failed to open git rev-list stdout".to_string()))?;
+    let mut reader = BufReader::new(stdout);
+
+    // Oldest child date of every commit whose children have been seen but which
+    // has not been read itself yet (the walk frontier)
+    let mut oldest_child: HashMap<String, i64> = HashMap::new();
+    let mut skewed = 0u64;
+
+    let mut line = String::with_capacity(128);
+    while reader.read_line(&mut line)? > 0 {
+        // Commit lines are "<timestamp> <hash> <parents>..."
+        let mut parts = line.split_ascii_whitespace();
+        let ts = parts.next().and_then(|s| s.parse::<i64>().ok());
+        let hash = parts.next();
+        if let (Some(ts), Some(hash)) = (ts, hash) {
+            let next = oldest_child.remove(hash).unwrap_or(i64::MAX);
+            let ts = if ts > next {
+                // Dated after one of its children: never the tip at any date
+                skewed += 1;
+                next
+            } else {
+                ts
+            };
+            // --parents lists every parent, even with --first-parent
+            let parents = if history.first_parent { 1 } else { usize::MAX };
+            for parent in parts.take(parents) {
+                let entry = oldest_child.entry(parent.to_string()).or_insert(ts);
+                *entry = (*entry).min(ts);
+            }
+
+            // Targets are descending: select those with ts <= target < next
+            let start = target_timestamps.partition_point(|&t| t >= next);
+            let end = target_timestamps.partition_point(|&t| t >= ts);
+            for commits in tips_at.iter_mut().take(end).skip(start) {
+                commits.push((ts, hash.to_string()));
+            }
+        }
+        line.clear();
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(GitSizeError::Command(
+            "Failed to walk repository history".to_string(),
+        ));
+    }
+    warn_clock_skew(skewed);
+
+    Ok(tips_at)
+}
+
+/// Warn, once per run, that `skewed` commits are dated after one of their children.
+fn warn_clock_skew(skewed: u64) {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    if skewed > 0 && !WARNED.swap(true, Ordering::Relaxed) {
+        eprintln!(
+            "⚠️  Warning: {} commits are dated after one of their children (clock skew).\n\
+             They are sampled as if dated like their oldest child.",
+            skewed
+        );
+    }
+}
+
+/// Measure the packed disk usage of the objects reachable from a commit and
+/// `extra_tips`.
+fn disk_usage(git_dir: &Path, commit_hash: &str, extra_tips: &[String]) -> Result<u64> {
+    if commit_hash.is_empty() {
+        return Err(GitSizeError::Validation(
+            "Commit hash cannot be empty".to_string(),
+        ));
+    }
+
+    // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
+    let disk_usage_output = git_command(git_dir)
+        .args([
+            "rev-list",
+            "--objects",
+            "--disk-usage",
+            "--use-bitmap-index",
+            "--stdin",
+        ])
+        .arg("--")
+        .output_with_input(revisions_input(commit_hash, extra_tips, &[]))
+        .map_err(|e| GitSizeError::Command(format!("Failed to get disk usage: {}", e)))?;
+
+    if !disk_usage_output.status.success() {
+        return Err(GitSizeError::Command(
+            "Failed to get disk usage".to_string(),
+        ));
+    }
+
+    // The last line contains the total disk usage in bytes
+    // Optimized: avoid String allocation and correctly handle potential multi-line output
+    Ok(std::str::from_utf8(&disk_usage_output.stdout)
+        .ok()
+        .and_then(|s| s.trim().lines().last())
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .unwrap_or(0))
+}
+
+/// Measure the packed size of the objects added since the previous sample point.
+///
+/// Objects reachable from `sample` but not from `previous` are counted, so
+/// rewrites and deleted-then-readded content show up even when the cumulative
+/// size barely moves. The first sample point counts everything reachable, its
+/// `cumulative_size`.
+///
+/// Measured as the size of both points together minus the already measured
+/// cumulative size of the previous one: `git rev-list <new> ^<old>` without
+/// bitmaps only excludes the trees of `<old>` itself, and would count content
+/// restored from older history again.
+fn measure_added_size(
+    git_dir: &Path,
+    sample: &SamplePoint,
+    cumulative_size: u64,
+    previous: Option<(&SamplePoint, u64)>,
+) -> Result<u64> {
+    let Some((prev, prev_size)) = previous else {
+        return Ok(cumulative_size);
+    };
+    let mut tips = sample.extra_tips.clone();
+    tips.push(prev.commit_hash.clone());
+    tips.extend(prev.extra_tips.iter().cloned());
+    let both = disk_usage(git_dir, &sample.commit_hash, &tips)?;
+    Ok(both.saturating_sub(prev_size))
+}
+
+/// Calculate the size of objects reachable from a specific commit.
+///
+/// In whole-repository mode `extra_tips` holds the other ref tips of the sample,
+/// and the union of objects reachable from all of them is measured.
+///
+/// This function uses git commands via `std::process::Command` to:
+/// 1. Measure the packed disk usage using `git rev-list --objects --disk-usage`.
+/// 2. (Optional) Measure the uncompressed size of all blobs using a pipeline
+///    of `git rev-list` and `git cat-file`.
+fn measure_size_at_commit(
+    git_dir: &Path,
+    commit_hash: &str,
+    extra_tips: &[String],
+    debug: bool,
+    calculate_uncompressed: bool,
+) -> Result<(u64, Option<u64>)> {
+    // Basic validation
+    if commit_hash.is_empty() {
+        return Err(GitSizeError::Validation(
+            "Commit hash cannot be empty".to_string(),
+        ));
+    }
+
+    // Get packed disk usage using git rev-list --disk-usage
+    let packed_size = disk_usage(git_dir, commit_hash, extra_tips)?;
+
+    // Calculate uncompressed size only if requested (it's slower)
+    let uncompressed_size = if calculate_uncompressed {
+        // Optimized: use --filter=object:type=blob to skip trees, and --no-object-names to reduce output.
+        // Also use OS-level piping between processes to avoid the overhead of a manual Rust pipe thread.
+        // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
+        let mut rev_list = git_command(git_dir)
+            .args([
+                "rev-list",
+                "--objects",
+                "--filter=object:type=blob",
+                "--no-object-names",
+                "--use-bitmap-index",
+                "--stdin",
+            ])
+            .arg("--")
+            .stdout(Stdio::piped())
+            .spawn_with_input(revisions_input(commit_hash, extra_tips, &[]))
+            .map_err(|e| GitSizeError::Command(format!("Failed to spawn git rev-list: {}", e)))?;
+
+        let rev_list_stdout = rev_list.stdout.take().ok_or_else(|| {
+            GitSizeError::Command("Failed to open git rev-list stdout".to_string())
+        })?;
+
+        let mut cat_file = git_command(git_dir)
+            .args(["cat-file", "--batch-check=%(objecttype) %(objectsize)"])
+            .stdin(Stdio::from(rev_list_stdout))
+            .stdout(Stdio::piped())
+            .spawn_tracked()
+            .map_err(|e| GitSizeError::Command(format!("Failed to spawn git cat-file: {}", e)))?;
+
+        let stdout = cat_file.stdout.take().ok_or_else(|| {
+            GitSizeError::Command("Failed to open git cat-file stdout".to_string())
+        })?;
+
+        let mut total = 0u64;
+        let mut blob_count = 0u64;
+        let mut object_count = 0u64;
+
+        let mut reader = BufReader::new(stdout);
+        let mut line = String::with_capacity(128);
+        while reader.read_line(&mut line)? > 0 {
+            object_count += 1;
+            // Optimized: use split_ascii_whitespace and reused buffer
+            // git cat-file --batch-check output is "<type> <size>"
+            let mut parts = line.split_ascii_whitespace();
+            if let Some("blob") = parts.next() {
+                if let Some(size_str) = parts.next() {
+                    if let Ok(s_u64) = size_str.parse::<u64>() {
+                        total += s_u64;
+                        blob_count += 1;
+                    }
+                }
+            }
+            line.clear();
+        }
+
+        // Clean up processes
+        cat_file.wait().map_err(|e| {
+            GitSizeError::Command(format!("Failed to wait for git cat-file: {}", e))
+        })?;
+        rev_list.wait().map_err(|e| {
+            GitSizeError::Command(format!("Failed to wait for git rev-list: {}", e))
+        })?;
+
+        if debug {
+            println!("  Objects: {}, Blobs: {}", object_count, blob_count);
+            println!(
+                "  Packed size: {}, Uncompressed size: {}",
+                format_size(packed_size),
+                format_size(total)
+            );
+        }
+
+        Some(total)
+    } else {
+        if debug {
+            println!("  Packed size: {}", format_size(packed_size));
+        }
+        None
+    };
+
+    Ok((packed_size, uncompressed_size))
+}
+
+/// Measure the packed size, uncompressed size and count of reachable objects per object type.
+///
+/// Uses a `git rev-list --objects` | `git cat-file --batch-check` pipeline over
+/// all objects (commits, trees, blobs and tags) reachable from the commit(s).
+fn measure_object_stats(
+    git_dir: &Path,
+    commit_hash: &str,
+    extra_tips: &[String],
+) -> Result<ObjectStats> {
+    if commit_hash.is_empty() {
+        return Err(GitSizeError::Validation(
+            "Commit hash cannot be empty".to_string(),
+        ));
+    }
+
+    // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
+    let mut rev_list = git_command(git_dir)
+        .args([
+            "rev-list",
+            "--objects",
+            "--no-object-names",
+            "--use-bitmap-index",
+            "--stdin",
+        ])
+        .arg("--")
+        .stdout(Stdio::piped())
+        .spawn_with_input(revisions_input(commit_hash, extra_tips, &[]))
+        .map_err(|e| GitSizeError::Command(format!("Failed to spawn git rev-list: {}", e)))?;
+
+    let rev_list_stdout = rev_list
+        .stdout
+        .take()
+        .ok_or_else(|| GitSizeError::Command("Failed to open git rev-list stdout".to_string()))?;
+
+    let mut cat_file = git_command(git_dir)
+        .args([
+            "cat-file",
+            "--batch-check=%(objecttype) %(objectsize) %(objectsize:disk)",
+        ])
+        .stdin(Stdio::from(rev_list_stdout))
+        .stdout(Stdio::piped())
+        .spawn_tracked()
+        .map_err(|e| GitSizeError::Command(format!("Failed to spawn git cat-file: {}", e)))?;
+
+    let stdout = cat_file
+        .stdout
+        .take()
+        .ok_or_else(|| GitSizeError::Command("Failed to open git cat-file stdout".to_string()))?;
+
+    let mut sizes = ObjectStats::default();
+    let mut reader = BufReader::new(stdout);
+    let mut line = String::with_capacity(64);
+    while reader.read_line(&mut line)? > 0 {
+        // Output is "<type> <size> <disk size>"
+        let mut parts = line.split_ascii_whitespace();
+        if let (Some(object_type), Some(size), Some(disk_size)) =
+            (parts.next(), parts.next(), parts.next())
+        {
+            if let (Ok(size), Ok(disk_size)) = (size.parse::<u64>(), disk_size.parse::<u64>()) {
+                sizes.packed.add(object_type, disk_size);
+                sizes.uncompressed.add(object_type, size);
+                sizes.counts.add(object_type, 1);
+            }
+        }
+        line.clear();
+    }
+
+    cat_file
+        .wait()
+        .map_err(|e| GitSizeError::Command(format!("Failed to wait for git cat-file: {}", e)))?;
+    let status = rev_list
+        .wait()
+        .map_err(|e| GitSizeError::Command(format!("Failed to wait for git rev-list: {}", e)))?;
+    if !status.success() {
+        return Err(GitSizeError::Command(
+            "Failed to list reachable objects".to_string(),
+        ));
+    }
+
+    Ok(sizes)
+}
+
+/// Stream every blob reachable from a commit with its path and sizes.
+///
+/// Runs a `git rev-list --objects` | `git cat-file --batch-check` pipeline and
+/// calls `f(blob_id, path, size, disk_size)` for each blob. Each blob is reported
+/// once, with the first path git found it at. Blobs reachable from any commit in
+/// `exclude` are skipped. Bitmaps are not used since they carry no path information.
+fn for_each_reachable_blob(
+    git_dir: &Path,
+    commit_hash: &str,
+    extra_tips: &[String],
+    exclude: &[String],
+    mut f: impl FnMut(&str, &str, u64, u64),
+) -> Result<()> {
+    if commit_hash.is_empty() {
+        return Err(GitSizeError::Validation(
+            "Commit hash cannot be empty".to_string(),
+        ));
+    }
+
+    // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
+    let mut rev_list = git_command(git_dir)
+        .args([
+            "rev-list",
+            "--objects",
+            "--filter=object:type=blob",
+            "--stdin",
+        ])
+        .arg("--")
+        .stdout(Stdio::piped())
+        .spawn_with_input(revisions_input(commit_hash, extra_tips, exclude))
+        .map_err(|e| GitSizeError::Command(format!("Failed to spawn git rev-list: {}", e)))?;
+
+    let rev_list_stdout = rev_list
+        .stdout
+        .take()
+        .ok_or_else(|| GitSizeError::Command("Failed to open git rev-list stdout".to_string()))?;
+
+    // %(rest) echoes the path that follows the object name in the rev-list output
+    let mut cat_file = git_command(git_dir)
+        .args([
+            "cat-file",
+            "--batch-check=%(objectname) %(objecttype) %(objectsize) %(objectsize:disk) %(rest)",
+        ])
+        .stdin(Stdio::from(rev_list_stdout))
+        .stdout(Stdio::piped())
+        .spawn_tracked()
+        .map_err(|e| GitSizeError::Command(format!("Failed to spawn git cat-file: {}", e)))?;
+
+    let stdout = cat_file
+        .stdout
+        .take()
+        .ok_or_else(|| GitSizeError::Command("Failed to open git cat-file stdout".to_string()))?;
+
+    let mut reader = BufReader::new(stdout);
+    let mut line = String::with_capacity(256);
+    while reader.read_line(&mut line)? > 0 {
+        // Output is "<oid> <type> <size> <disk size> <path>"; the path may contain spaces
+        let mut parts = line.trim_end_matches(['\n', '\r']).splitn(5, ' ');
+        if let (Some(oid), Some("blob"), Some(size), Some(disk_size)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        {
+            if let (Ok(size), Ok(disk_size)) = (size.parse::<u64>(), disk_size.parse::<u64>()) {
+                f(oid, parts.next().unwrap_or(""), size, disk_size);
+            }
+        }
+        line.clear();
+    }
+
+    cat_file
+        .wait()
+        .map_err(|e| GitSizeError::Command(format!("Failed to wait for git cat-file: {}", e)))?;
+    let status = rev_list
+        .wait()
+        .map_err(|e| GitSizeError::Command(format!("Failed to wait for git rev-list: {}", e)))?;
+    if !status.success() {
+        return Err(GitSizeError::Command(
+            "Failed to list reachable objects".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Find the largest blobs (by disk size) introduced between two sample points.
+///
+/// Lists the blobs reachable from `to` but not from `from` (`git rev-list
+/// --objects <to> ^<from>`), keeps the `count` largest, then finds the commit
+/// that introduced each of them with a single `git log --raw` walk of the interval.
+fn find_top_blobs(
+    git_dir: &Path,
+    from: &SamplePoint,
+    to: &SamplePoint,
+    count: usize,
+) -> Result<Vec<IntroducedBlob>> {
+    let mut exclude = vec![from.commit_hash.clone()];
+    exclude.extend(from.extra_tips.iter().cloned());
+
+    // Min-heap of the largest blobs seen so far
+    let mut largest: BinaryHeap<Reverse<(u64, String, String, u64)>> = BinaryHeap::new();
+    for_each_reachable_blob(
+        git_dir,
+        &to.commit_hash,
+        &to.extra_tips,
+        &exclude,
+        |oid, path, size, disk_size| {
+            if largest.len() < count {
+                largest.push(Reverse((
+                    disk_size,
+                    oid.to_string(),
+                    path.to_string(),
+                    size,
+                )));
+            } else if largest.peek().is_some_and(|Reverse(min)| disk_size > min.0) {
+                largest.pop();
+                largest.push(Reverse((
+                    disk_size,
+                    oid.to_string(),
+                    path.to_string(),
+                    size,
+                )));
+            }
+        },
+    )?;
+
+    let mut blobs: Vec<IntroducedBlob> = largest
+        .into_sorted_vec()
+        .into_iter()
+        .map(|Reverse((disk_size, blob, path, size))| IntroducedBlob {
+            start_date: from.date.clone(),
+            end_date: to.date.clone(),
+            blob,
+            path,
+            size,
+            disk_size,
+            commit: None,
+        })
+        .collect();
+    if blobs.is_empty() {
+        return Ok(blobs);
+    }
+
+    // Walk the interval newest first: the last commit adding a blob is the oldest one.
+    // `--cc` lists the files of merges that differ from every parent, i.e. blobs
+    // introduced by the merge itself.
+    // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
+    let mut log = git_command(git_dir)
+        .args([
+            "log",
+            "--no-show-signature",
+            "--no-renames",
+            "--raw",
+            "--cc",
+            "--no-abbrev",
+            "--format=%H",
+            "--stdin",
+        ])
+        .arg("--")
+        .stdout(Stdio::piped())
+        .spawn_with_input(revisions_input(&to.commit_hash, &to.extra_tips, &exclude))
+        .map_err(|e| GitSizeError::Command(format!("Failed to spawn git log: {}", e)))?;
+
+    let stdout = log
+        .stdout
+        .take()
+        .ok_or_else(|| GitSizeError::Command("Failed to open git log stdout".to_string()))?;
+    let mut reader = BufReader::new(stdout);
+
+    let mut current_commit = String::new();
+    let mut line = String::with_capacity(256);
+    while reader.read_line(&mut line)? > 0 {
+        // Raw diff lines are ":<old mode> <new mode> <old oid> <new oid> <status>\t<path>",
+        // merges have one colon, mode and oid per parent before the result's
+        let raw = line.trim_start_matches(':');
+        let parents = line.len() - raw.len();
+        if parents > 0 {
+            if let Some(new_oid) = raw.split_ascii_whitespace().nth(2 * parents + 1) {
+                for blob in blobs.iter_mut().filter(|b| b.blob == new_oid) {
+                    blob.commit = Some(current_commit.clone());
+                }
+            }
+        } else if !line.trim().is_empty() {
+            current_commit.clear();
+            current_commit.push_str(line.trim());
+        }
+        line.clear();
+    }
+    let _ = log.wait();
+
+    Ok(blobs)
+}
+
+/// Read the rows of an existing CSV output (`--append`), both as written and as
+/// measurements.
+///
+/// The file must have been written with the same columns as `columns`.
+fn read_csv_output(
+    path: &Path,
+    columns: &CsvColumns,
+) -> Result<(Vec<csv::StringRecord>, Vec<SizeMeasurement>)> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let header: Vec<String> = reader.headers()?.iter().map(str::to_string).collect();
+    let expected = columns.header();
+    if header != expected {
+        return Err(GitSizeError::Validation(format!(
+            "Cannot append to {}: its columns ({}) differ from the columns of this run ({}). \
+             Use the options of the run that created it.",
+            path.display(),
+            header.join(","),
+            expected.join(",")
+        )));
+    }
+
+    let mut records = Vec::new();
+    let mut measurements = Vec::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record?;
+        let measurement = columns.parse(&record).ok_or_else(|| {
+            GitSizeError::Validation(format!(
+                "Cannot append to {}: invalid row {}",
+                path.display(),
+                line + 2
+            ))
+        })?;
+        records.push(record);
+        measurements.push(measurement);
+    }
+    Ok((records, measurements))
+}
+
+/// Sample points of this run that are not in the `existing` rows yet (`--append`).
+///
+/// Tag sample points are kept when no row has their tag, whatever its date or
+/// version order, and commit sample points when they follow the last row's
+/// commit index. Date sample points are kept after the last row's date. A last
+/// row that is not on this run's dates measured the end of the history of the
+/// run that wrote it: it is removed from `existing` and measured again, so that
+/// such rows do not pile up between the regular sample dates.
+fn samples_to_append(
+    samples: Vec<SamplePoint>,
+    existing: &mut Vec<SizeMeasurement>,
+) -> Vec<SamplePoint> {
+    let Some(last) = existing.last() else {
+        return samples;
+    };
+    if last.label.is_some() {
+        let labels: HashSet<&str> = existing
+            .iter()
+            .filter_map(|row| row.label.as_deref())
+            .collect();
+        return samples
+            .into_iter()
+            .filter(|sample| {
+                !sample
+                    .label
+                    .as_deref()
+                    .is_some_and(|label| labels.contains(label))
+            })
+            .collect();
+    }
+    if let Some(last_index) = last.commit_index {
+        return samples
+            .into_iter()
+            .filter(|sample| sample.commit_index.is_some_and(|index| index > last_index))
+            .collect();
+    }
+
+    if !samples.iter().any(|sample| sample.date == last.date) {
+        existing.pop();
+    }
+    match existing.last() {
+        Some(last) => samples
+            .into_iter()
+            .filter(|sample| sample.date > last.date)
+            .collect(),
+        None => samples,
+    }
+}
+
+/// The existing row that each sample point to append follows (`--added`), if
+/// it is more recent than the sample point before it.
+///
+/// Dated sample points are appended after all existing rows, while new tags
+/// (`--sample-by tags`) may be older than some of them.
+fn preceding_rows<'a>(
+    existing: &'a [SizeMeasurement],
+    samples: &[SamplePoint],
+) -> Vec<Option<&'a SizeMeasurement>> {
+    samples
+        .iter()
+        .enumerate()
+        .map(|(idx, sample)| {
+            let row = existing
+                .iter()
+                .filter(|row| {
+                    (row.date.as_str(), row.commit_time)
+                        <= (sample.date.as_str(), sample.commit_time)
+                })
+                .max_by_key(|row| (row.date.as_str(), row.commit_time))?;
+            match idx.checked_sub(1).map(|prev| &samples[prev]) {
+                Some(prev)
+                    if (prev.date.as_str(), prev.commit_time)
+                        >= (row.date.as_str(), row.commit_time) =>
+                {
+                    None
+                }
+                _ => Some(row),
+            }
+        })
+        .collect()
+}
+
+/// Sample point of an existing CSV row, as the previous sample point of an
+/// appended one (`--added`).
+///
+/// Rows record the measured commit, and tag rows the tag, whose object is
+/// measured as well when it is annotated. In whole-repository mode the commit
+/// is only the newest ref tip, so the tips as of its date are found again.
+fn existing_sample_point(
+    repo: &Repository,
+    history: &History,
+    row: &SizeMeasurement,
+) -> Result<Option<SamplePoint>> {
+    if history.whole_repository && row.label.is_none() {
+        let commit_date = DateTime::from_timestamp(row.commit_time, 0).ok_or_else(|| {
+            GitSizeError::Validation(format!(
+                "Invalid commit date of the {} row: {}",
+                row.date, row.commit_time
+            ))
+        })?;
+        return Ok(sample_points_at(repo.path(), history, vec![commit_date])?.pop());
+    }
+    let tag = row.label.as_ref().and_then(|label| {
+        let reference = repo.find_reference(&format!("refs/tags/{}", label)).ok()?;
+        repo.find_tag(reference.target()?).ok()
+    });
+    Ok(Some(SamplePoint {
+        date: row.date.clone(),
+        commit_hash: row.commit_hash.clone(),
+        commit_time: row.commit_time,
+        extra_tips: tag.map(|tag| tag.id().to_string()).into_iter().collect(),
+        label: row.label.clone(),
+        commit_index: row.commit_index,
+    }))
+}
+
+/// Write a CSV file atomically: records go to a temporary file next to `path`,
+/// which then replaces it, so that an interrupted run never leaves a truncated file.
+///
+/// The temporary file is named after the process, so that concurrent runs do
+/// not write to the same one, and synced to disk before the rename, so that a
+/// crash cannot leave an empty file in place of the previous one.
+fn write_csv_atomically<F>(path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&mut Writer<std::fs::File>) -> Result<()>,
+{
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| GitSizeError::Validation(format!("Invalid output path: {:?}", path)))?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let written = Writer::from_path(&temp_path)
+        .map_err(GitSizeError::from)
+        .and_then(|mut wtr| {
+            write(&mut wtr)?;
+            wtr.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+            Ok(())
+        })
+        .and_then(|()| std::fs::rename(&temp_path, path).map_err(GitSizeError::from));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    written
+}
+
+/// Write the `--top-blobs` CSV report.
+fn write_top_blobs_csv(path: &Path, blobs: &[IntroducedBlob]) -> Result<()> {
+    let mut wtr = Writer::from_path(path)?;
+    wtr.write_record([
+        "start-date",
+        "end-date",
+        "commit",
+        "path",
+        "blob",
+        "disk-size",
+        "size",
+    ])?;
+    for blob in blobs {
+        wtr.write_record([
+            blob.start_date.as_str(),
+            blob.end_date.as_str(),
+            blob.commit.as_deref().unwrap_or(""),
+            blob.path.as_str(),
+            blob.blob.as_str(),
+            &blob.disk_size.to_string(),
+            &blob.size.to_string(),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Measure reachable blob sizes grouped according to `grouping`.
+fn measure_breakdown(
+    git_dir: &Path,
+    commit_hash: &str,
+    extra_tips: &[String],
+    grouping: &Grouping,
+) -> Result<BTreeMap<String, GroupSize>> {
+    let mut groups: BTreeMap<String, GroupSize> = BTreeMap::new();
+    for_each_reachable_blob(
+        git_dir,
+        commit_hash,
+        extra_tips,
+        &[],
+        |_, path, size, disk_size| {
+            let group = grouping.group_of(path);
+            // Only allocate the group name the first time it is seen
+            let entry = match groups.get_mut(group.as_ref()) {
+                Some(entry) => entry,
+                None => groups.entry(group.into_owned()).or_default(),
+            };
+            entry.packed += disk_size;
+            entry.uncompressed += size;
+        },
+    )?;
+    Ok(groups)
+}
+
+/// Directory prefix of `path` limited to `depth` components.
+///
+/// Files at the repository root are grouped under `"."`.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(path_prefix("src/bin/main.rs", 1), "src");
+/// assert_eq!(path_prefix("src/bin/main.rs", 5), "src/bin");
+/// assert_eq!(path_prefix("README.md", 1), ".");
+/// ```
+fn path_prefix(path: &str, depth: usize) -> &str {
+    let dir = match path.rfind('/') {
+        Some(idx) => &path[..idx],
+        None => return ".",
+    };
+    match dir.match_indices('/').nth(depth.saturating_sub(1)) {
+        Some((idx, _)) => &dir[..idx],
+        None => dir,
+    }
+}
+
+/// Extension of the file name in `path`, without the leading dot.
+///
+/// Dotfiles such as `.gitignore` have no extension.
+fn file_extension(path: &str) -> Option<&str> {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    match name.rfind('.') {
+        Some(idx) if idx > 0 && idx + 1 < name.len() => Some(&name[idx + 1..]),
+        _ => None,
+    }
+}
+
+/// Match `path` against a glob pattern.
+///
+/// `*` and `?` match within a single path component and `**` matches across
+/// directories. Like in `.gitignore`, a pattern without a `/` is matched against
+/// the file name only, so `*.png` matches PNG files in any directory.
+fn glob_match(pattern: &str, path: &str) -> bool {
+    fn matches(pattern: &[u8], text: &[u8]) -> bool {
+        match pattern.split_first() {
+            None => text.is_empty(),
+            Some((b'*', rest)) if rest.first() == Some(&b'*') => {
+                // "**" matches across directories, and "**/" also matches no directory at all
+                let rest = &rest[1..];
+                rest.strip_prefix(b"/")
+                    .is_some_and(|after| matches(after, text))
+                    || (0..=text.len()).any(|i| matches(rest, &text[i..]))
+            }
+            Some((b'*', rest)) => {
+                for i in 0..=text.len() {
+                    if matches(rest, &text[i..]) {
+                        return true;
+                    }
+                    if text.get(i) == Some(&b'/') {
+                        break;
+                    }
+                }
+                false
+            }
+            Some((b'?', rest)) => {
+                text.first().is_some_and(|&c| c != b'/') && matches(rest, &text[1..])
+            }
+            Some((&c, rest)) => text.first() == Some(&c) && matches(rest, &text[1..]),
+        }
+    }
+
+    if pattern.contains('/') {
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        matches(pattern.as_bytes(), path.as_bytes())
+    } else {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        matches(pattern.as_bytes(), name.as_bytes())
+    }
+}
+
+/// Load a category map from a CSV file of `glob,category` lines.
+///
+/// Lines starting with `#` are comments. Patterns are tried in file order.
+fn load_categories(path: &Path) -> Result<Vec<(String, String)>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .comment(Some(b'#'))
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+
+    let mut categories = Vec::new();
+    for record in rdr.records() {
+        let record = record?;
+        match (record.get(0), record.get(1)) {
+            (Some(pattern), Some(category)) if !pattern.is_empty() && !category.is_empty() => {
+                categories.push((pattern.to_string(), category.to_string()));
+            }
+            _ => {
+                return Err(GitSizeError::Validation(format!(
+                    "Invalid category line in {:?}: expected \"glob,category\"",
+                    path
+                )))
+            }
+        }
+    }
+
+    if categories.is_empty() {
+        return Err(GitSizeError::Validation(format!(
+            "No categories found in {:?}",
+            path
+        )));
+    }
+    Ok(categories)
+}
+
+/// Write a long-format breakdown CSV (`date,<group column>,packed,uncompressed`).
+///
+/// With `commit_columns`, the date column is named `target-date` and followed by
+/// `commit` and `commit-date` columns.
+fn write_breakdown_csv(
+    path: &Path,
+    group_column: &str,
+    data: &[&Breakdown],
+    commit_columns: bool,
+) -> Result<()> {
+    let labeled = data.iter().any(|breakdown| breakdown.label.is_some());
+    let indexed = data
+        .iter()
+        .any(|breakdown| breakdown.commit_index.is_some());
+    let mut wtr = Writer::from_path(path)?;
+    let mut header = if commit_columns {
+        vec!["target-date", "commit", "commit-date"]
+    } else {
+        vec!["date"]
+    };
+    if labeled {
+        header.push("label");
+    }
+    if indexed {
+        header.push("commit-index");
+    }
+    header.extend([group_column, "packed", "uncompressed"]);
+    wtr.write_record(&header)?;
+    for breakdown in data {
+        let mut point = vec![breakdown.date.clone()];
+        if commit_columns {
+            point.push(breakdown.commit_hash.clone());
+            point.push(format_commit_date(breakdown.commit_time));
+        }
+        if labeled {
+            point.push(breakdown.label.clone().unwrap_or_default());
+        }
+        if indexed {
+            point.push(breakdown.commit_index.unwrap_or(0).to_string());
+        }
+        for (group, size) in &breakdown.groups {
+            let mut record = point.clone();
+            record.extend([
+                group.clone(),
+                size.packed.to_string(),
+                size.uncompressed.to_string(),
+            ]);
+            wtr.write_record(&record)?;
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Format a commit date for the CSV output, as an RFC 3339 UTC timestamp.
+fn format_commit_date(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_default()
+}
+
+/// Format a byte count into a human-readable string (B, KB, MB, GB).
+///
+/// This function converts a size in bytes to a human-readable format
+/// using decimal prefixes (1 KB = 1000 bytes).
+///
+/// # Arguments
+///
+/// * `size` - The size in bytes to format
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(format_size(0), "0 B");
+/// assert_eq!(format_size(1500), "1.50 KB");
+/// assert_eq!(format_size(2500000), "2.50 MB");
+/// assert_eq!(format_size(5500000000), "5.50 GB");
+/// ```
+fn format_size(size: u64) -> String {
+    const KB: u64 = 1_000;
+    const MB: u64 = 1_000_000;
+    const GB: u64 = 1_000_000_000;
+
+    if size >= GB {
+        format!("{:.2} GB", size as f64 / GB as f64)
+    } else if size >= MB {
+        format!("{:.2} MB", size as f64 / MB as f64)
+    } else if size >= KB {
+        format!("{:.2} KB", size as f64 / KB as f64)
+    } else {
+        format!("{} B", size)
+    }
+}
+
+/// Generate a cumulative size over time plot using the `plotters` library.
+///
+/// This creates a PNG file at `output_path` displaying repository growth
+/// based on the provided size measurement data. Sizes added per interval (if
+/// measured) are overlaid as bars, and labeled sample points (tags) are
+/// marked. With `plot_counts`, object and blob counts are drawn against a
+/// secondary y-axis.
+fn generate_plot(data: &[SizeMeasurement], output_path: &Path, plot_counts: bool) -> Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+
+    let mut dated: Vec<(i64, &SizeMeasurement)> = data
+        .iter()
+        .filter_map(|d| {
+            NaiveDate::parse_from_str(&d.date, "%Y-%m-%d")
+                .ok()
+                .and_then(|dt| {
+                    dt.and_hms_opt(0, 0, 0)
+                        .map(|naive| naive.and_utc().timestamp())
+                        .map(|ts| (ts, d))
+                })
+        })
+        .collect();
+    // Rows read back from a CSV in version order are drawn along the history
+    dated.sort_by_key(|(ts, _)| *ts);
+    let plot_data: Vec<(i64, u64)> = dated
+        .iter()
+        .map(|(ts, d)| (*ts, d.cumulative_size))
+        .collect();
+    // (timestamp, object count, blob count)
+    let count_data: Vec<(i64, u64, u64)> = if plot_counts {
+        dated
+            .iter()
+            .filter_map(|(ts, d)| {
+                d.object_stats.map(|stats| {
+                    let counts = stats.counts;
+                    (*ts, counts.values().iter().sum(), counts.blobs)
+                })
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    if plot_data.is_empty() {
+        return Ok(());
+    }
+
+    let min_ts = plot_data.iter().map(|(t, _)| *t).min().unwrap_or(0);
+    let max_ts = plot_data.iter().map(|(t, _)| *t).max().unwrap_or(0);
+    // (interval start, interval end, added size), skipping the first sample whose
+    // "added" size is the whole initial size
+    let added_data: Vec<(i64, i64, u64)> = dated
+        .windows(2)
+        .filter_map(|pair| {
+            pair[1]
+                .1
+                .added_size
+                .map(|added| (pair[0].0, pair[1].0, added))
+        })
+        .collect();
+
+    let max_size = plot_data.iter().map(|(_, s)| *s).max().unwrap_or(0);
+    let max_count = count_data.iter().map(|(_, o, _)| *o).max().unwrap_or(0);
+
+    // Add margins
+    let time_margin = ((max_ts - min_ts) / 20).max(86400 * 30);
+    let size_margin = (max_size / 10).max(1000);
+    let count_margin = (max_count / 10).max(10);
+
+    let root = BitMapBackend::new(output_path, (1200, 600)).into_drawing_area();
+    root.fill(&WHITE)
+        .map_err(|e| GitSizeError::Plot(e.to_string()))?;
+
+    let mut builder = ChartBuilder::on(&root);
+    builder
+        .caption(
+            "Git Repository Size Over Time",
+            ("sans-serif", 30).into_font(),
+        )
+        .margin(5)
+        .x_label_area_size(60)
+        .y_label_area_size(80);
+    if plot_counts {
+        builder.right_y_label_area_size(80);
+    }
+    let x_range = (min_ts - time_margin)..(max_ts + time_margin);
+    let mut chart = builder
+        .build_cartesian_2d(x_range.clone(), 0u64..(max_size + size_margin))
+        .map_err(|e| GitSizeError::Plot(e.to_string()))?
+        .set_secondary_coord(x_range, 0u64..(max_count + count_margin));
+
+    chart
+        .configure_mesh()
+        .light_line_style(TRANSPARENT)
+        .bold_line_style(BLACK.mix(0.3))
+        .x_labels(10)
+        .y_labels(10)
+        .x_label_formatter(&|v| {
+            DateTime::from_timestamp(*v, 0)
+                .map(|dt| dt.format("%Y-%m").to_string())
+                .unwrap_or_default()
+        })
+        .y_label_formatter(&|v| format_size(*v))
+        .draw()
+        .map_err(|e| GitSizeError::Plot(e.to_string()))?;
+
+    if !added_data.is_empty() {
+        let bar_color = RGBColor(255, 140, 0);
+        chart
+            .draw_series(added_data.iter().map(|(start, end, added)| {
+                // Leave a small gap between neighbouring intervals
+                let gap = (end - start) / 10;
+                Rectangle::new(
+                    [(start + gap, 0), (end - gap, *added)],
+                    bar_color.mix(0.5).filled(),
+                )
+            }))
+            .map_err(|e| GitSizeError::Plot(e.to_string()))?
+            .label("Added per Interval")
+            .legend(move |(x, y)| {
+                Rectangle::new([(x, y - 5), (x + 20, y + 5)], bar_color.mix(0.5).filled())
+            });
+    }
+
+    chart
+        .draw_series(LineSeries::new(
+            plot_data.iter().map(|(t, s)| (*t, *s)),
+            BLUE,
+        ))
+        .map_err(|e| GitSizeError::Plot(e.to_string()))?
+        .label("Cumulative Size")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
+
+    // Mark labeled sample points (tags), thinning labels out to keep them readable
+    let labeled: Vec<(i64, u64, &str)> = dated
+        .iter()
+        .filter_map(|(ts, d)| {
+            d.label
+                .as_deref()
+                .map(|label| (*ts, d.cumulative_size, label))
+        })
+        .collect();
+    let label_step = labeled.len().div_ceil(MAX_PLOT_LABELS).max(1);
+    chart
+        .draw_series(labeled.iter().step_by(label_step).map(|(ts, size, label)| {
+            EmptyElement::at((*ts, *size))
+                + Circle::new((0, 0), 3, BLUE.filled())
+                + Text::new(label.to_string(), (4, -14), ("sans-serif", 12).into_font())
+        }))
+        .map_err(|e| GitSizeError::Plot(e.to_string()))?;
+
+    if plot_counts {
+        chart
+            .configure_secondary_axes()
+            .y_desc("Objects")
+            .y_labels(10)
+            .draw()
+            .map_err(|e| GitSizeError::Plot(e.to_string()))?;
+
+        chart
+            .draw_secondary_series(LineSeries::new(
+                count_data.iter().map(|(t, objects, _)| (*t, *objects)),
+                RED,
+            ))
+            .map_err(|e| GitSizeError::Plot(e.to_string()))?
+            .label("Object Count")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
+
+        chart
+            .draw_secondary_series(LineSeries::new(
+                count_data.iter().map(|(t, _, blobs)| (*t, *blobs)),
+                GREEN,
+            ))
+            .map_err(|e| GitSizeError::Plot(e.to_string()))?
+            .label("Blob Count")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], GREEN));
+    }
+
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()
+        .map_err(|e| GitSizeError::Plot(e.to_string()))?;
+
+    root.present()
+        .map_err(|e| GitSizeError::Plot(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Write the CSV, plot and summary of a breakdown run.
+fn write_breakdown_outputs(
+    args: &Args,
+    output: &Path,
+    repo_path: &Path,
+    group_column: &str,
+    tag_sort: TagSort,
+    breakdowns: &[Breakdown],
+) -> Result<()> {
+    println!("Writing CSV to {}", output.display());
+    let rows = tag_sort.csv_rows(breakdowns, |breakdown| breakdown.label.as_deref());
+    write_breakdown_csv(output, group_column, &rows, !args.no_commit_columns)?;
+
+    if let Some(plot_path) = &args.plot {
+        println!("Generating plot: {}", plot_path.display());
+        generate_breakdown_plot(breakdowns, plot_path)?;
+        println!("Plot saved to {}", plot_path.display());
+    }
+
+    println!("\n=== Summary ===");
+    println!("Repository: {}", repo_path.display());
+    println!("Sample points: {}", breakdowns.len());
+    if let Some(last) = breakdowns.last() {
+        let mut groups: Vec<(&String, &GroupSize)> = last.groups.iter().collect();
+        groups.sort_by_key(|(_, size)| Reverse(size.packed));
+        println!("Largest groups by {} ({}):", group_column, last.date);
+        for (group, size) in groups.iter().take(MAX_PLOT_GROUPS) {
+            println!(
+                "  {}: {} packed, {} uncompressed",
+                group,
+                format_size(size.packed),
+                format_size(size.uncompressed)
+            );
+        }
+    }
+
+    println!("\nOutput written to {}", output.display());
+    Ok(())
+}
+
+/// Generate a stacked-area plot of a size breakdown using the `plotters` library.
+///
+/// Each band shows the packed size of one group over time. Only the groups
+/// largest at the last sample get their own band; the rest are merged into "other".
+fn generate_breakdown_plot(data: &[Breakdown], output_path: &Path) -> Result<()> {
+    let timestamps: Vec<i64> = data
+        .iter()
+        .filter_map(|d| {
+            NaiveDate::parse_from_str(&d.date, "%Y-%m-%d")
+                .ok()
+                .and_then(|dt| dt.and_hms_opt(0, 0, 0))
+                .map(|naive| naive.and_utc().timestamp())
+        })
+        .collect();
+
+    if timestamps.is_empty() || timestamps.len() != data.len() {
+        return Ok(());
+    }
+
+    // Pick the groups that get their own band, largest first
+    let mut ranked: Vec<(&String, u64)> = data
+        .last()
+        .map(|last| last.groups.iter().map(|(g, s)| (g, s.packed)).collect())
+        .unwrap_or_default();
+    ranked.sort_by_key(|(_, size)| Reverse(*size));
+    let mut bands: Vec<String> = ranked
+        .iter()
+        .take(MAX_PLOT_GROUPS)
+        .map(|(g, _)| (*g).clone())
+        .collect();
+    let has_other = data
+        .iter()
+        .any(|d| d.groups.keys().any(|g| !bands.contains(g)));
+    if has_other {
+        bands.push("other".to_string());
+    }
+
+    // Stacked tops of each band at each sample, from the bottom band upwards
+    let mut stacked: Vec<Vec<u64>> = Vec::with_capacity(bands.len());
+    let mut totals = vec![0u64; data.len()];
+    for (band_idx, band) in bands.iter().enumerate() {
+        let is_other = has_other && band_idx == bands.len() - 1;
+        for (total, d) in totals.iter_mut().zip(data) {
+            *total += if is_other {
+                d.groups
+                    .iter()
+                    .filter(|(g, _)| !bands[..band_idx].contains(g))
+                    .map(|(_, s)| s.packed)
+                    .sum::<u64>()
+            } else {
+                d.groups.get(band).map_or(0, |s| s.packed)
+            };
+        }
+        stacked.push(totals.clone());
+    }
+
+    let min_ts = timestamps.iter().copied().min().unwrap_or(0);
+    let max_ts = timestamps.iter().copied().max().unwrap_or(0);
+    let max_size = totals.iter().copied().max().unwrap_or(0);
+
+    // Add margins
+    let time_margin = ((max_ts - min_ts) / 20).max(86400 * 30);
+    let size_margin = (max_size / 10).max(1000);
+
+    let root = BitMapBackend::new(output_path, (1200, 600)).into_drawing_area();
+    root.fill(&WHITE)
+        .map_err(|e| GitSizeError::Plot(e.to_string()))?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(
+            "Git Repository Size Breakdown Over Time",
+            ("sans-serif", 30).into_font(),
+        )
+        .margin(5)
+        .x_label_area_size(60)
+        .y_label_area_size(80)
+        .build_cartesian_2d(
+            (min_ts - time_margin)..(max_ts + time_margin),
+            0u64..(max_size + size_margin),
+        )
+        .map_err(|e| GitSizeError::Plot(e.to_string()))?;
+
+    chart
+        .configure_mesh()
+        .light_line_style(TRANSPARENT)
+        .bold_line_style(BLACK.mix(0.3))
+        .x_labels(10)
+        .y_labels(10)
+        .x_label_formatter(&|v| {
+            DateTime::from_timestamp(*v, 0)
+                .map(|dt| dt.format("%Y-%m").to_string())
+                .unwrap_or_default()
+        })
+        .y_label_formatter(&|v| format_size(*v))
+        .draw()
+        .map_err(|e| GitSizeError::Plot(e.to_string()))?;
+
+    // Draw the tallest (top) band first so lower bands are painted over it
+    for (band_idx, band) in bands.iter().enumerate().rev() {
+        let color = Palette99::pick(band_idx).to_rgba();
+        chart
+            .draw_series(AreaSeries::new(
+                timestamps
+                    .iter()
+                    .copied()
+                    .zip(stacked[band_idx].iter().copied()),
+                0,
+                color.mix(0.8).filled(),
+            ))
+            .map_err(|e| GitSizeError::Plot(e.to_string()))?
+            .label(band.as_str())
+            .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], color.filled()));
+    }
+
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()
+        .map_err(|e| GitSizeError::Plot(e.to_string()))?;
+
+    root.present()
         .map_err(|e| GitSizeError::Plot(e.to_string()))?;
 
-    let mut chart = ChartBuilder::on(&root)
-        .caption(
-            "Git Repository Size Over Time",
-            ("sans-serif", 30).into_font(),
+    Ok(())
+}
+
+/// A single commit whose reachable size exceeds its first parent's by more than
+/// the bisect threshold
+struct SizeJump {
+    /// The commit found
+    commit: String,
+    /// The commit before it in first-parent order (or the `from` commit)
+    parent: String,
+    /// Reachable packed size at `parent`
+    size_before: u64,
+    /// Reachable packed size at `commit`
+    size_after: u64,
+}
+
+/// Resolve an end of the bisect range: a revision, or a date, meaning the
+/// last first-parent ancestor of `tip` dated at or before it.
+///
+/// A value that is neither, or both (e.g. a tag named like a date), is an error.
+fn resolve_bisect_point(repo: &Repository, value: &str, tip: &str) -> Result<String> {
+    let timestamp = match (resolve_revision(repo, value), parse_date(value)) {
+        (Ok(commit), Err(_)) => return Ok(commit.id().to_string()),
+        (Err(_), Ok(timestamp)) => timestamp,
+        (Ok(_), Ok(_)) => {
+            return Err(GitSizeError::Validation(format!(
+                "{:?} is both a revision and a date; write the revision as a full \
+                 ref name (e.g. refs/tags/{}) or the date in RFC 3339 format",
+                value, value
+            )))
+        }
+        (Err(_), Err(_)) => {
+            return Err(GitSizeError::Validation(format!(
+                "{:?} is neither a revision nor a date (YYYY-MM-DD, RFC 3339 or \
+                 relative, e.g. 2.years.ago)",
+                value
+            )))
+        }
+    };
+
+    // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
+    let output = git_command(repo.path())
+        .args(["rev-list", "--first-parent", "--max-count=1"])
+        .arg(format!("--before=@{}", timestamp))
+        .arg(tip)
+        .arg("--")
+        .output_tracked()
+        .map_err(|e| GitSizeError::Command(format!("Failed to run git rev-list: {}", e)))?;
+    let hash = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if hash.is_empty() {
+        return Err(GitSizeError::Validation(format!(
+            "No commit found at or before {:?}",
+            value
+        )));
+    }
+    Ok(hash)
+}
+
+/// Binary search the first-parent history between `from` and `to` for the
+/// first commit whose reachable size exceeds its predecessor's by more than
+/// `threshold`.
+///
+/// Intervals whose size change exceeds the threshold are halved, older half
+/// first, so a jump is usually found with `O(log n)` size measurements; halves
+/// growing by less than the threshold are skipped. Returns `None` when no
+/// single commit exceeds the threshold, e.g. when growth is gradual.
+fn bisect_size_jump(
+    git_dir: &Path,
+    from: &str,
+    to: &str,
+    threshold: Threshold,
+) -> Result<Option<SizeJump>> {
+    // Commits after `from` up to `to`, oldest first, with `from` prepended
+    // SECURITY: Use "--" to separate revisions from paths to prevent argument injection.
+    let output = git_command(git_dir)
+        .args(["rev-list", "--first-parent", "--reverse", to])
+        .arg(format!("^{}", from))
+        .arg("--")
+        .output_tracked()
+        .map_err(|e| GitSizeError::Command(format!("Failed to run git rev-list: {}", e)))?;
+    if !output.status.success() {
+        return Err(GitSizeError::Command(format!(
+            "git rev-list failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let mut commits = vec![from.to_string()];
+    commits.extend(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| line.trim().to_string()),
+    );
+
+    let mut sizes: HashMap<usize, u64> = HashMap::new();
+    let mut size = |idx: usize| -> Result<u64> {
+        if let Some(size) = sizes.get(&idx) {
+            return Ok(*size);
+        }
+        let (packed, _) = measure_size_at_commit(git_dir, &commits[idx], &[], false, false)?;
+        sizes.insert(idx, packed);
+        Ok(packed)
+    };
+
+    // Intervals that may contain the jump, the oldest one on top
+    let mut intervals = vec![(0, commits.len() - 1)];
+    while let Some((low, high)) = intervals.pop() {
+        let (low_size, high_size) = (size(low)?, size(high)?);
+        // Sizes only grow along history, so an interval without enough growth
+        // cannot contain the jump
+        if !threshold.exceeded(low_size, high_size.saturating_sub(low_size)) {
+            continue;
+        }
+        if high - low == 1 {
+            return Ok(Some(SizeJump {
+                commit: commits[high].clone(),
+                parent: commits[low].clone(),
+                size_before: low_size,
+                size_after: high_size,
+            }));
+        }
+
+        let mid = low + (high - low) / 2;
+        println!(
+            "  {} commits left, {} at {}",
+            high - low,
+            format_size(size(mid)?),
+            &commits[mid][..12.min(commits[mid].len())]
+        );
+        intervals.push((mid, high));
+        intervals.push((low, mid));
+    }
+
+    Ok(None)
+}
+
+/// Run the `bisect` subcommand: find and describe the commit behind a size jump.
+fn run_bisect(bisect: &BisectArgs) -> Result<()> {
+    let (_, repo) = open_repository(&bisect.repo_path)?;
+    let git_dir = repo.path();
+
+    let to = resolve_bisect_point(&repo, &bisect.to, "HEAD")?;
+    let from = resolve_bisect_point(&repo, &bisect.from, &to)?;
+    let from_oid = git2::Oid::from_str(&from)?;
+    let to_oid = git2::Oid::from_str(&to)?;
+    if from_oid == to_oid || !repo.graph_descendant_of(to_oid, from_oid)? {
+        return Err(GitSizeError::Validation(format!(
+            "{:?} must be an ancestor of {:?}",
+            bisect.from, bisect.to
+        )));
+    }
+
+    println!("Bisecting size between {} and {}...", from, to);
+    let Some(jump) = bisect_size_jump(git_dir, &from, &to, bisect.threshold)? else {
+        println!("\nNo single commit increased the size by more than the threshold.");
+        return Ok(());
+    };
+
+    let commit = repo.find_commit(git2::Oid::from_str(&jump.commit)?)?;
+    let author = commit.author();
+    let date = DateTime::from_timestamp(author.when().seconds(), 0)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_default();
+
+    println!(
+        "\nSize jump: +{} ({} -> {})",
+        format_size(jump.size_after - jump.size_before),
+        format_size(jump.size_before),
+        format_size(jump.size_after)
+    );
+    println!("commit {}", jump.commit);
+    println!(
+        "Author: {} <{}>",
+        author.name().unwrap_or("unknown"),
+        author.email().unwrap_or("unknown")
+    );
+    println!("Date:   {}", date);
+    println!();
+    for line in commit.message().unwrap_or("").trim_end().lines() {
+        println!("    {}", line);
+    }
+
+    // Describe the blobs the commit brought in
+    let sample = |hash: &str| SamplePoint {
+        date: String::new(),
+        commit_hash: hash.to_string(),
+        commit_time: 0,
+        extra_tips: Vec::new(),
+        label: None,
+        commit_index: None,
+    };
+    let blobs = find_top_blobs(
+        git_dir,
+        &sample(&jump.parent),
+        &sample(&jump.commit),
+        bisect.top_n,
+    )?;
+    if !blobs.is_empty() {
+        println!("\nLargest new blobs:");
+        for blob in &blobs {
+            println!(
+                "  {} on disk ({} uncompressed)  {}",
+                format_size(blob.disk_size),
+                format_size(blob.size),
+                blob.path
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Open the repository at `path` (relative to the current directory).
+///
+/// Returns the absolute repository path along with the repository.
+fn open_repository(path: &Path) -> Result<(PathBuf, Repository)> {
+    // Resolve and validate repo path
+    let repo_path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
+
+    if !repo_path.exists() {
+        return Err(GitSizeError::Validation(format!(
+            "Repository path does not exist: {:?}",
+            repo_path
+        )));
+    }
+
+    // Open repository. Regular checkouts, bare repositories and mirrors are all
+    // supported, and a working tree is also discovered from any of its subdirectories.
+    let repo = Repository::open(&repo_path)
+        .or_else(|_| {
+            Repository::open_ext(
+                &repo_path,
+                RepositoryOpenFlags::empty(),
+                std::iter::empty::<&OsStr>(),
+            )
+        })
+        .map_err(|e| {
+            let context = if repo_path.join(".git").exists() {
+                format!(
+                    "Cannot open repository at {:?}. The .git directory exists but may be corrupted or inaccessible. \
+                    Try running 'git fsck' to check repository integrity. \
+                    Git error: {}",
+                    repo_path, e
+                )
+            } else {
+                format!(
+                    "Cannot open repository at {:?}. Path is not a git repository or bare repository. \
+                    Make sure you're pointing to a valid git repository. \
+                    Git error: {}",
+                    repo_path, e
+                )
+            };
+            GitSizeError::Validation(context)
+        })?;
+
+    Ok((repo_path, repo))
+}
+
+fn main() -> Result<()> {
+    install_interrupt_handler();
+    let result = run();
+    // Failures caused by the interruption (e.g. killed git processes) are not reported
+    if result.is_err() && interrupted() {
+        std::process::exit(EXIT_INTERRUPTED);
+    }
+    result
+}
+
+fn run() -> Result<()> {
+    let args = Args::parse();
+
+    // Measurements run on the global thread pool
+    if let Some(jobs) = args.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs as usize)
+            .build_global()
+            .map_err(|e| GitSizeError::Validation(format!("Cannot start {} jobs: {}", jobs, e)))?;
+    }
+
+    let output = match (&args.command, args.output.as_deref()) {
+        (Some(Commands::Bisect(bisect)), _) => return run_bisect(bisect),
+        (None, Some(output)) => output,
+        (None, None) => unreachable!("clap requires --output without a subcommand"),
+    };
+    let (repo_path, repo) = open_repository(&args.repo_path)?;
+
+    // Breakdown grouping, validated before the (potentially long) analysis starts
+    let grouping = if let Some(depth) = args.breakdown_depth {
+        Some(Grouping::Directory(depth as usize))
+    } else if args.by_extension {
+        Some(Grouping::Extension)
+    } else if let Some(path) = &args.categories {
+        Some(Grouping::Categories(load_categories(path)?))
+    } else {
+        None
+    };
+
+    // Tag options only apply to tag sampling, not to an explicit `--sample-by date`
+    if args.sample_by != SampleBy::Tags && (args.tag_pattern.is_some() || args.tag_sort.is_some()) {
+        return Err(GitSizeError::Validation(
+            "--tag-pattern and --tag-sort require --sample-by tags".to_string(),
+        ));
+    }
+
+    // All git subprocesses operate on the git directory itself
+    let git_dir = repo.path().to_path_buf();
+
+    // Progress bar for analysis phase - use indeterminate spinner during commit reading
+    let analysis_pb = ProgressBar::new_spinner();
+    analysis_pb.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} [{elapsed_precise}] {msg}")
+            .map_err(|e| {
+                GitSizeError::Validation(format!("Failed to set progress style: {}", e))
+            })?,
+    );
+    analysis_pb.enable_steady_tick(std::time::Duration::from_millis(100));
+    analysis_pb.set_message("Reading commit history...");
+
+    // Select the analyzed history: a single revision, or every selected ref
+    let mut history = if args.all || args.branches || args.tags {
+        History::from_refs(&repo, args.all, args.branches, args.tags)?
+    } else {
+        History::from_rev(&repo, &args.rev)?
+    };
+    if args.first_parent {
+        history.first_parent = true;
+        history.description.push_str(", first-parent");
+    }
+    if args.date_source == DateSource::Author {
+        history.date_source = DateSource::Author;
+        history.description.push_str(", author dates");
+    }
+
+    // Get commit range
+    let range = get_commit_range(&repo, &history, &analysis_pb)?;
+    let total_commits = range.total_commits;
+
+    let tag_sort = args.tag_sort.unwrap_or_default();
+    let sampling = SamplingOptions {
+        monthly: args.monthly,
+        yearly: args.yearly,
+        interval: args.interval,
+        samples: args.samples,
+        calendar: args.calendar,
+        every_n_commits: args.every_n_commits,
+        since: args.since.as_deref().map(parse_date).transpose()?,
+        until: args.until.as_deref().map(parse_date).transpose()?,
+        tags: (args.sample_by == SampleBy::Tags).then(|| TagSampling {
+            pattern: args.tag_pattern.clone(),
+            sort: tag_sort,
+        }),
+    };
+    let (first_dt, last_dt) = sampling.span(&history, &range)?;
+
+    let duration = last_dt - first_dt;
+    let years = duration.num_days() as f64 / DAYS_PER_YEAR;
+
+    // Determine sampling strategy
+    let sampling_method = sampling.describe(years);
+    analysis_pb.set_message(format!(
+        "Found {} commits ({} to {}, {:.1} years) - {} sampling",
+        total_commits,
+        first_dt.format("%Y-%m-%d"),
+        last_dt.format("%Y-%m-%d"),
+        years,
+        sampling_method
+    ));
+
+    // Generate sample points
+    let samples = match &sampling.tags {
+        Some(tags) => generate_tag_sample_points(&repo, &history, &sampling, tags)?,
+        None => generate_sample_points(&git_dir, &history, &range, &sampling)?,
+    };
+    analysis_pb.set_message(format!("Generated {} sample points", samples.len()));
+    analysis_pb.finish_with_message("Analysis complete");
+
+    let columns = CsvColumns {
+        commit: !args.no_commit_columns,
+        label: sampling.tags.is_some(),
+        commit_index: sampling.every_n_commits.is_some(),
+        uncompressed: args.uncompressed,
+        added: args.added,
+        object_types: args.by_type,
+        counts: args.counts,
+    };
+
+    // With --append, only sample points newer than the existing rows are measured
+    let (mut existing_records, mut existing) = if args.append && output.exists() {
+        read_csv_output(output, &columns)?
+    } else {
+        (Vec::new(), Vec::new())
+    };
+    let mut samples = samples;
+    if let Some(last) = existing.last() {
+        if args.added && !columns.commit {
+            return Err(GitSizeError::Validation(
+                "--added with --append needs the commit columns in the existing CSV".to_string(),
+            ));
+        }
+        let last_name = last.name().to_string();
+        samples = samples_to_append(samples, &mut existing);
+        if existing.len() < existing_records.len() {
+            existing_records.truncate(existing.len());
+            println!(
+                "Replacing the last row ({}), which measured the end of the history as of its run",
+                last_name
+            );
+        }
+        match existing.last() {
+            Some(last) if samples.is_empty() => {
+                println!(
+                    "No sample points after {}: {} is up to date",
+                    last.name(),
+                    output.display()
+                );
+                return Ok(());
+            }
+            Some(last) => println!(
+                "Appending {} sample points after {}",
+                samples.len(),
+                last.name()
+            ),
+            None => {}
+        }
+    }
+
+    // With --max-memory, fewer measurements run at once than there are threads
+    let budget = match args.max_memory {
+        Some(max_memory) => {
+            let cat_file = args.uncompressed
+                || args.by_type
+                || args.counts
+                || args.top_blobs.is_some()
+                || grouping.is_some();
+            let budget = MemoryBudget::new(max_memory, count_objects(&git_dir)?, cat_file);
+            if budget.slots < rayon::current_num_threads() {
+                println!(
+                    "Memory budget: {} measurements at once (about {} each)",
+                    budget.slots,
+                    format_size(budget.per_measurement)
+                );
+            }
+            Some(budget)
+        }
+        None => None,
+    };
+
+    // Progress bar for sampling phase - shows complete commits count
+    let pb = ProgressBar::new(samples.len() as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template(
+                "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})",
+            )
+            .map_err(|e| GitSizeError::Validation(format!("Failed to set progress style: {}", e)))?
+            .progress_chars("=>-"),
+    );
+    pb.enable_steady_tick(std::time::Duration::from_millis(100));
+
+    // Wrap progress bar in Arc for thread-safe updates
+    // indicatif::ProgressBar is already thread-safe using atomics
+    let pb = std::sync::Arc::new(pb);
+
+    if let Some(grouping) = &grouping {
+        let breakdowns: Vec<Breakdown> = samples
+            .par_iter()
+            .map(|sample| {
+                check_interrupted()?;
+                let _permit = budget.as_ref().map(MemoryBudget::acquire);
+                let groups =
+                    measure_breakdown(&git_dir, &sample.commit_hash, &sample.extra_tips, grouping)?;
+                pb.inc(1);
+                Ok(Breakdown {
+                    date: sample.date.clone(),
+                    commit_hash: sample.commit_hash.clone(),
+                    commit_time: sample.commit_time,
+                    label: sample.label.clone(),
+                    commit_index: sample.commit_index,
+                    groups,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if let Ok(inner_pb) = std::sync::Arc::try_unwrap(pb) {
+            inner_pb.finish_with_message("Sampling complete");
+        }
+
+        return write_breakdown_outputs(
+            &args,
+            output,
+            &repo_path,
+            grouping.column(),
+            tag_sort,
+            &breakdowns,
+        );
+    }
+
+    // Sizes of sample points measured by earlier runs are read from the cache
+    let cache = if args.no_cache {
+        None
+    } else {
+        let cache_dir = args
+            .cache_dir
+            .clone()
+            .unwrap_or_else(|| repo.path().join(CACHE_DIR_NAME));
+        match MeasurementCache::open(&cache_dir, &repo) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                eprintln!(
+                    "⚠️  Warning: Cannot use the measurement cache in {}: {}",
+                    cache_dir.display(),
+                    e
+                );
+                None
+            }
+        }
+    };
+    // Measurements are journaled as they complete, for --resume after an interruption
+    let journal = Journal::open(output, args.resume)?;
+    let measure = |sample: &SamplePoint| {
+        measure_sample_point(
+            &git_dir,
+            sample,
+            &args,
+            cache.as_ref(),
+            Some(&journal),
+            budget.as_ref(),
+        )
+    };
+
+    let measured = (|| -> Result<(Vec<SizeMeasurement>, usize)> {
+        // Measure sizes in parallel for better performance
+        // Using rayon to process multiple sample points concurrently
+        let mut results: Vec<SizeMeasurement> = samples
+            .par_iter()
+            .map(|sample| {
+                let measurement = measure(sample)?;
+
+                // Thread-safe progress bar increment (indicatif uses atomics internally)
+                pb.inc(1);
+
+                Ok(measurement)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let refined = if let Some(threshold) = args.refine_threshold {
+            let refinement = Refinement {
+                threshold,
+                min_interval: args.min_interval.unwrap_or(DEFAULT_MIN_INTERVAL),
+                max_samples: args
+                    .max_samples
+                    .map_or(DEFAULT_MAX_SAMPLES, |max| max as usize),
+            };
+            refine_samples(
+                &git_dir,
+                &history,
+                &refinement,
+                measure,
+                &mut samples,
+                &mut results,
+                &pb,
+            )?
+        } else {
+            0
+        };
+        Ok((results, refined))
+    })();
+
+    // Measurements done before a failure or an interruption are kept as well
+    if let Some(cache) = &cache {
+        if let Err(e) = cache.save(&repo) {
+            eprintln!(
+                "⚠️  Warning: Failed to update the measurement cache in {}: {}",
+                cache.dir.display(),
+                e
+            );
+        }
+    }
+    let (mut results, refined) = match measured {
+        Err(e) if interrupted() => {
+            pb.abandon();
+            journal.report_interrupted();
+            return Err(e);
+        }
+        measured => measured.map_err(|e| journal.fail(e))?,
+    };
+
+    // Added sizes depend on the previous sample point, known once refinement is done
+    if args.added {
+        // Appended sample points may follow an existing row rather than the
+        // sample point before them
+        let mut preceding = Vec::with_capacity(samples.len());
+        for row in preceding_rows(&existing, &samples) {
+            let point = match row {
+                Some(row) => existing_sample_point(&repo, &history, row)
+                    .map_err(|e| journal.fail(e))?
+                    .map(|point| (point, row.cumulative_size)),
+                None => None,
+            };
+            preceding.push(point);
+        }
+        let added_sizes = samples
+            .par_iter()
+            .enumerate()
+            .map(|(idx, sample)| {
+                let previous = match (&preceding[idx], idx.checked_sub(1)) {
+                    (Some((point, size)), _) => Some((point, *size)),
+                    (None, Some(prev)) => Some((&samples[prev], results[prev].cumulative_size)),
+                    (None, None) => None,
+                };
+                let previous_sample = previous.map(|(prev, _)| prev);
+                if let Some(size) = journal.get_added(sample, previous_sample) {
+                    return Ok(size);
+                }
+                check_interrupted()?;
+                let _permit = budget.as_ref().map(MemoryBudget::acquire);
+                let size =
+                    measure_added_size(&git_dir, sample, results[idx].cumulative_size, previous)?;
+                journal.record_added(sample, previous_sample, size)?;
+                Ok(size)
+            })
+            .collect::<Result<Vec<_>>>();
+        let added_sizes = match added_sizes {
+            Err(e) if interrupted() => {
+                pb.abandon();
+                journal.report_interrupted();
+                return Err(e);
+            }
+            added_sizes => added_sizes.map_err(|e| journal.fail(e))?,
+        };
+        for (result, added_size) in results.iter_mut().zip(added_sizes) {
+            result.added_size = Some(added_size);
+        }
+    }
+
+    // Finish progress bar
+    if let Ok(inner_pb) = std::sync::Arc::try_unwrap(pb) {
+        inner_pb.finish_with_message("Sampling complete");
+    }
+
+    // Find the largest blobs introduced in each interval
+    let top_blobs: Vec<IntroducedBlob> = if let Some(top_blobs_path) = &args.top_blobs {
+        println!("Finding largest blobs introduced per interval...");
+        let top_blobs: Vec<IntroducedBlob> = samples
+            .par_windows(2)
+            .map(|pair| {
+                check_interrupted()?;
+                let _permit = budget.as_ref().map(MemoryBudget::acquire);
+                find_top_blobs(&git_dir, &pair[0], &pair[1], args.top_n)
+            })
+            .collect::<Result<Vec<_>>>()
+            .map_err(|e| journal.fail(e))?
+            .into_iter()
+            .flatten()
+            .collect();
+        println!("Writing largest blobs to {}", top_blobs_path.display());
+        write_top_blobs_csv(top_blobs_path, &top_blobs).map_err(|e| journal.fail(e))?;
+        top_blobs
+    } else {
+        Vec::new()
+    };
+
+    // Write CSV, keeping existing rows as they were written
+    println!("Writing CSV to {}", output.display());
+    write_csv_atomically(output, |wtr| {
+        wtr.write_record(columns.header())?;
+        for record in &existing_records {
+            wtr.write_record(record)?;
+        }
+        for data in tag_sort.csv_rows(&results, |data| data.label.as_deref()) {
+            wtr.write_record(columns.record(data))?;
+        }
+        Ok(())
+    })
+    .map_err(|e| journal.fail(e))?;
+    let measured = results.len();
+    let resumed = journal.hits();
+    journal.remove()?;
+    let results: Vec<SizeMeasurement> = existing.into_iter().chain(results).collect();
+
+    // Generate plot
+    if let Some(plot_path) = &args.plot {
+        println!("Generating plot: {}", plot_path.display());
+        generate_plot(&results, plot_path, args.plot_counts)?;
+        println!("Plot saved to {}", plot_path.display());
+    }
+
+    // Print summary
+    println!("\n=== Summary ===");
+    println!("Repository: {}", repo_path.display());
+    println!("History: {}", history.description);
+    println!("Total commits analyzed: {}", range.total_commits);
+    println!(
+        "Time span: {} to {} ({:.1} years)",
+        first_dt.format("%Y-%m-%d"),
+        last_dt.format("%Y-%m-%d"),
+        years
+    );
+    println!("Sample points: {}", results.len());
+    println!("Sampling method: {}", sampling_method);
+    if args.refine_threshold.is_some() {
+        println!("Refinement: {} sample points added", refined);
+    }
+    if args.append {
+        println!("Appended: {} sample points", measured);
+    }
+    if args.resume {
+        println!(
+            "Resumed: {} sample points from the checkpoint journal",
+            resumed
+        );
+    }
+    if let Some(cache) = &cache {
+        println!(
+            "Cache: {} of {} sample points reused ({})",
+            cache.hits(),
+            measured,
+            cache.dir.display()
+        );
+    }
+
+    if let Some(first) = results.first() {
+        println!(
+            "Initial size ({}): {}",
+            first.name(),
+            format_size(first.cumulative_size)
+        );
+    }
+    if let Some(last) = results.last() {
+        println!(
+            "Final size ({}): {}",
+            last.name(),
+            format_size(last.cumulative_size)
+        );
+    }
+
+    if results.len() >= 2 {
+        if let (Some(first), Some(last)) = (results.first(), results.last()) {
+            let growth = last.cumulative_size.saturating_sub(first.cumulative_size);
+            println!("Total growth: {}", format_size(growth));
+        }
+    }
+
+    if args.uncompressed {
+        if let Some(last) = results.last() {
+            if let Some(uncompressed) = last.uncompressed_size {
+                println!("Final uncompressed size: {}", format_size(uncompressed));
+            }
+        }
+    }
+
+    if let Some(types) = results
+        .last()
+        .and_then(|last| last.object_stats)
+        .filter(|_| args.by_type)
+    {
+        println!("Final size by object type (packed / uncompressed):");
+        for (name, packed, uncompressed) in [
+            ("commits", types.packed.commits, types.uncompressed.commits),
+            ("trees", types.packed.trees, types.uncompressed.trees),
+            ("blobs", types.packed.blobs, types.uncompressed.blobs),
+            ("tags", types.packed.tags, types.uncompressed.tags),
+        ] {
+            println!(
+                "  {}: {} / {}",
+                name,
+                format_size(packed),
+                format_size(uncompressed)
+            );
+        }
+    }
+
+    if args.added {
+        // The first sample's added size is the initial size, not growth
+        if let Some(largest) = results
+            .iter()
+            .skip(1)
+            .filter(|d| d.added_size.is_some())
+            .max_by_key(|d| d.added_size)
+        {
+            println!(
+                "Largest interval growth (up to {}): {}",
+                largest.name(),
+                format_size(largest.added_size.unwrap_or(0))
+            );
+        }
+    }
+
+    if !top_blobs.is_empty() {
+        let mut largest: Vec<&IntroducedBlob> = top_blobs.iter().collect();
+        largest.sort_by_key(|blob| Reverse(blob.disk_size));
+        println!("Largest blobs introduced:");
+        for blob in largest.iter().take(args.top_n) {
+            println!(
+                "  {} ({} packed, {} uncompressed) between {} and {}{}",
+                blob.path,
+                format_size(blob.disk_size),
+                format_size(blob.size),
+                blob.start_date,
+                blob.end_date,
+                blob.commit
+                    .as_deref()
+                    .map(|commit| format!(" in {}", &commit[..commit.len().min(12)]))
+                    .unwrap_or_default()
+            );
+        }
+    }
+
+    if args.counts {
+        if let Some(stats) = results.last().and_then(|last| last.object_stats) {
+            let counts = stats.counts;
+            println!(
+                "Final object count: {} ({} commits, {} trees, {} blobs, {} tags)",
+                counts.values().iter().sum::<u64>(),
+                counts.commits,
+                counts.trees,
+                counts.blobs,
+                counts.tags
+            );
+        }
+    }
+
+    println!("\nOutput written to {}", output.display());
+    if let Some(plot_path) = &args.plot {
+        println!("Plot saved to {}", plot_path.display());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size_bytes() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1), "1 B");
+        assert_eq!(format_size(999), "999 B");
+    }
+
+    #[test]
+    fn test_format_size_kilobytes() {
+        assert_eq!(format_size(1_000), "1.00 KB");
+        assert_eq!(format_size(1_500), "1.50 KB");
+        assert_eq!(format_size(999_999), "1000.00 KB");
+    }
+
+    #[test]
+    fn test_format_size_megabytes() {
+        assert_eq!(format_size(1_000_000), "1.00 MB");
+        assert_eq!(format_size(2_500_000), "2.50 MB");
+        assert_eq!(format_size(999_999_999), "1000.00 MB");
+    }
+
+    #[test]
+    fn test_format_size_gigabytes() {
+        assert_eq!(format_size(1_000_000_000), "1.00 GB");
+        assert_eq!(format_size(5_500_000_000), "5.50 GB");
+    }
+
+    #[test]
+    fn test_integration_minimal_repo() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-test-{}", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        // Initialize repo
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+
+        // Create initial commit
+        let tree_id = repo.index().unwrap().write_tree().unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+        let oid = repo
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                "initial commit",
+                &tree,
+                &[],
+            )
+            .unwrap();
+
+        // Test get_commit_range
+        let pb = ProgressBar::hidden();
+        let history = History::from_rev(&repo, "HEAD").unwrap();
+        let range = get_commit_range(&repo, &history, &pb).unwrap();
+        assert_eq!(range.total_commits, 1);
+
+        // Test sampling
+        let samples =
+            generate_sample_points(repo.path(), &history, &range, &SamplingOptions::default())
+                .unwrap();
+        assert!(!samples.is_empty());
+
+        // Test size measurement (at least check if it runs without error)
+        let (packed, _) =
+            measure_size_at_commit(repo.path(), &oid.to_string(), &[], false, false).unwrap();
+        assert!(packed > 0);
+
+        // Cleanup
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_generate_sample_points_yearly_strategy() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-yearly-test-{}", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+
+        // Create commits by appending content to the same file
+        for i in 0..50 {
+            let file_path = temp_dir.join("test.txt");
+            std::fs::write(&file_path, format!("Content {}\n", i)).unwrap();
+
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("test.txt")).unwrap();
+            index.write().unwrap();
+            let tree_id = index.write_tree().unwrap();
+            let tree = repo.find_tree(tree_id).unwrap();
+
+            let head = repo.head().ok();
+            let parent = head.as_ref().and_then(|h| h.peel_to_commit().ok());
+            let parents: Vec<&git2::Commit> = parent.iter().collect();
+
+            repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                &format!("commit {}", i),
+                &tree,
+                parents.as_slice(),
+            )
+            .unwrap();
+        }
+
+        let pb = ProgressBar::hidden();
+        let history = History::from_rev(&repo, "HEAD").unwrap();
+        let range = get_commit_range(&repo, &history, &pb).unwrap();
+
+        // Force monthly sampling for this test
+        let samples = generate_sample_points(
+            repo.path(),
+            &history,
+            &range,
+            &SamplingOptions {
+                monthly: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        // Should have at least one sample (the final commit)
+        // Note: Since all commits are created at nearly the same time,
+        // the sampling may only produce one point
+        assert!(!samples.is_empty());
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_generate_sample_points_forced_yearly() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir =
+            std::env::temp_dir().join(format!("git-size-force-yearly-test-{}", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+
+        // Create a few commits
+        for i in 0..5 {
+            let file_path = temp_dir.join("test.txt");
+            std::fs::write(&file_path, format!("Content {}\n", i)).unwrap();
+
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("test.txt")).unwrap();
+            index.write().unwrap();
+            let tree_id = index.write_tree().unwrap();
+            let tree = repo.find_tree(tree_id).unwrap();
+
+            let head = repo.head().ok();
+            let parent = head.as_ref().and_then(|h| h.peel_to_commit().ok());
+            let parents: Vec<&git2::Commit> = parent.iter().collect();
+
+            repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                &format!("commit {}", i),
+                &tree,
+                parents.as_slice(),
+            )
+            .unwrap();
+        }
+
+        let pb = ProgressBar::hidden();
+        let history = History::from_rev(&repo, "HEAD").unwrap();
+        let range = get_commit_range(&repo, &history, &pb).unwrap();
+
+        // Force yearly sampling
+        let samples = generate_sample_points(
+            repo.path(),
+            &history,
+            &range,
+            &SamplingOptions {
+                yearly: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        // Should have at least start and end
+        assert!(!samples.is_empty());
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_generate_sample_points_forced_monthly() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir =
+            std::env::temp_dir().join(format!("git-size-force-monthly-test-{}", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+
+        // Create commits
+        for i in 0..10 {
+            let file_path = temp_dir.join("test.txt");
+            std::fs::write(&file_path, format!("Content {}\n", i)).unwrap();
+
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("test.txt")).unwrap();
+            index.write().unwrap();
+            let tree_id = index.write_tree().unwrap();
+            let tree = repo.find_tree(tree_id).unwrap();
+
+            let head = repo.head().ok();
+            let parent = head.as_ref().and_then(|h| h.peel_to_commit().ok());
+            let parents: Vec<&git2::Commit> = parent.iter().collect();
+
+            repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                &format!("commit {}", i),
+                &tree,
+                parents.as_slice(),
+            )
+            .unwrap();
+        }
+
+        let pb = ProgressBar::hidden();
+        let history = History::from_rev(&repo, "HEAD").unwrap();
+        let range = get_commit_range(&repo, &history, &pb).unwrap();
+
+        // Force monthly sampling
+        let samples = generate_sample_points(
+            repo.path(),
+            &history,
+            &range,
+            &SamplingOptions {
+                monthly: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert!(!samples.is_empty());
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_csv_output_format() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-csv-test-{}", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let tree_id = repo.index().unwrap().write_tree().unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+        let _oid = repo
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                "initial commit",
+                &tree,
+                &[],
+            )
+            .unwrap();
+
+        let output_path = temp_dir.join("output.csv");
+
+        // Test CSV writing directly
+        let mut wtr = Writer::from_path(&output_path).unwrap();
+        wtr.write_record(["date", "cumulative-size"]).unwrap();
+        wtr.write_record(["2024-01-01", "1234"]).unwrap();
+        wtr.flush().unwrap();
+
+        // Verify CSV content
+        let content = std::fs::read_to_string(&output_path).unwrap();
+        assert!(content.contains("date,cumulative-size"));
+        assert!(content.contains("2024-01-01,1234"));
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_measure_size_with_uncompressed() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-uncomp-test-{}", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+
+        // Create a commit with some content
+        let file_path = temp_dir.join("test.txt");
+        std::fs::write(&file_path, "Hello, World! This is some test content.").unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("test.txt")).unwrap();
+        index.write().unwrap();
+        let tree_id = index.write_tree().unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+        let oid = repo
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                "commit with content",
+                &tree,
+                &[],
+            )
+            .unwrap();
+
+        // Test with uncompressed calculation
+        let (packed, uncompressed) =
+            measure_size_at_commit(repo.path(), &oid.to_string(), &[], false, true).unwrap();
+
+        assert!(packed > 0);
+        assert!(uncompressed.is_some());
+        assert!(uncompressed.unwrap() > 0);
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_error_handling_invalid_path() {
+        let invalid_path = PathBuf::from("/nonexistent/path/to/repo");
+        let result = Repository::open(&invalid_path);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_error_handling_empty_repo() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-empty-test-{}", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        // Initialize repo but don't create any commits
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+
+        // get_commit_range should return an error for empty repo
+        let pb = ProgressBar::hidden();
+        let result = History::from_rev(&repo, "HEAD")
+            .and_then(|history| get_commit_range(&repo, &history, &pb));
+        assert!(result.is_err());
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_multi_commit_repository() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-multi-test-{}", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+
+        // Create multiple commits by modifying the same file
+        let mut commits = Vec::new();
+        for i in 0..5 {
+            let file_path = temp_dir.join("test.txt");
+            std::fs::write(&file_path, format!("Content of file {}\n", i)).unwrap();
+
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("test.txt")).unwrap();
+            index.write().unwrap();
+            let tree_id = index.write_tree().unwrap();
+            let tree = repo.find_tree(tree_id).unwrap();
+
+            let head = repo.head().ok();
+            let parent = head.as_ref().and_then(|h| h.peel_to_commit().ok());
+            let parents: Vec<&git2::Commit> = parent.iter().collect();
+
+            let oid = repo
+                .commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    &format!("commit {}", i),
+                    &tree,
+                    parents.as_slice(),
+                )
+                .unwrap();
+            commits.push(oid);
+        }
+
+        // Test get_commit_range
+        let pb = ProgressBar::hidden();
+        let history = History::from_rev(&repo, "HEAD").unwrap();
+        let range = get_commit_range(&repo, &history, &pb).unwrap();
+        assert_eq!(range.total_commits, 5);
+
+        // Test sampling
+        let samples =
+            generate_sample_points(repo.path(), &history, &range, &SamplingOptions::default())
+                .unwrap();
+        assert!(!samples.is_empty());
+
+        // Test size measurement at different commits
+        for (i, commit_oid) in commits.iter().enumerate() {
+            let (packed, _) =
+                measure_size_at_commit(repo.path(), &commit_oid.to_string(), &[], false, false)
+                    .unwrap();
+            assert!(packed > 0, "Size measurement failed for commit {}", i);
+        }
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_commit_range_for_revision() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-rev-test-{}", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+
+        let mut commits = Vec::new();
+        for i in 0..3 {
+            let file_path = temp_dir.join("test.txt");
+            std::fs::write(&file_path, format!("Content {}\n", i)).unwrap();
+
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("test.txt")).unwrap();
+            index.write().unwrap();
+            let tree_id = index.write_tree().unwrap();
+            let tree = repo.find_tree(tree_id).unwrap();
+
+            let head = repo.head().ok();
+            let parent = head.as_ref().and_then(|h| h.peel_to_commit().ok());
+            let parents: Vec<&git2::Commit> = parent.iter().collect();
+
+            let oid = repo
+                .commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    &format!("commit {}", i),
+                    &tree,
+                    parents.as_slice(),
+                )
+                .unwrap();
+            commits.push(oid);
+        }
+        let first = repo.find_commit(commits[0]).unwrap();
+        repo.tag_lightweight("v1", first.as_object(), false)
+            .unwrap();
+
+        let pb = ProgressBar::hidden();
+
+        // A tag only covers the history up to the tagged commit
+        let history = History::from_rev(&repo, "v1").unwrap();
+        let range = get_commit_range(&repo, &history, &pb).unwrap();
+        assert_eq!(range.total_commits, 1);
+        assert_eq!(range.last_commit.id(), commits[0]);
+
+        // Revision expressions are accepted as well
+        let history = History::from_rev(&repo, "HEAD~1").unwrap();
+        let range = get_commit_range(&repo, &history, &pb).unwrap();
+        assert_eq!(range.total_commits, 2);
+        let samples =
+            generate_sample_points(repo.path(), &history, &range, &SamplingOptions::default())
+                .unwrap();
+        assert!(samples
+            .iter()
+            .all(|s| s.commit_hash != commits[2].to_string()));
+
+        // Unknown revisions are rejected before reaching the git CLI
+        assert!(History::from_rev(&repo, "no-such-branch").is_err());
+        assert!(History::from_rev(&repo, "--output=/tmp/x").is_err());
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_bare_repository() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-bare-test-{}.git", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        // Bare repositories have no index or working tree, so build the commit directly
+        let repo = git2::Repository::init_bare(&temp_dir).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let blob = repo.blob(b"Hello from a bare repository").unwrap();
+        let mut builder = repo.treebuilder(None).unwrap();
+        builder.insert("test.txt", blob, 0o100644).unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let oid = repo
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                "initial commit",
+                &tree,
+                &[],
+            )
+            .unwrap();
+        assert!(repo.is_bare());
+
+        let pb = ProgressBar::hidden();
+        let history = History::from_rev(&repo, "HEAD").unwrap();
+        let range = get_commit_range(&repo, &history, &pb).unwrap();
+        assert_eq!(range.total_commits, 1);
+
+        let samples =
+            generate_sample_points(repo.path(), &history, &range, &SamplingOptions::default())
+                .unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].commit_hash, oid.to_string());
+
+        let (packed, uncompressed) =
+            measure_size_at_commit(repo.path(), &oid.to_string(), &[], false, true).unwrap();
+        assert!(packed > 0);
+        assert_eq!(uncompressed, Some(28));
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_whole_repository_mode() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-all-test-{}", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+        let day = 86_400;
+        let start = 1_600_000_000;
+
+        // Commit a single file on top of `parents`, dated `time`
+        let commit =
+            |update_ref: Option<&str>, time: i64, content: &[u8], parents: &[git2::Oid]| {
+                let signature =
+                    git2::Signature::new("test", "test@example.com", &git2::Time::new(time, 0))
+                        .unwrap();
+                let blob = repo.blob(content).unwrap();
+                let mut builder = repo.treebuilder(None).unwrap();
+                builder.insert("data.bin", blob, 0o100644).unwrap();
+                let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+                let parents: Vec<git2::Commit> = parents
+                    .iter()
+                    .map(|p| repo.find_commit(*p).unwrap())
+                    .collect();
+                let parents: Vec<&git2::Commit> = parents.iter().collect();
+                repo.commit(
+                    update_ref, &signature, &signature, "commit", &tree, &parents,
+                )
+                .unwrap()
+            };
+
+        // main: c1 -> c3, feature: c1 -> c2 (never merged, carries a large blob)
+        let c1 = commit(Some("HEAD"), start, b"small", &[]);
+        let c2 = commit(None, start + 40 * day, &vec![b'x'; 100_000], &[c1]);
+        repo.branch("feature", &repo.find_commit(c2).unwrap(), false)
+            .unwrap();
+        let c3 = commit(Some("HEAD"), start + 70 * day, b"small again", &[c1]);
+
+        let pb = ProgressBar::hidden();
+        let history = History::from_refs(&repo, false, true, false).unwrap();
+        assert_eq!(history.tips.len(), 2);
+        let range = get_commit_range(&repo, &history, &pb).unwrap();
+        assert_eq!(range.total_commits, 3);
+        assert_eq!(range.last_commit.id(), c3);
+
+        let samples = generate_sample_points(
+            repo.path(),
+            &history,
+            &range,
+            &SamplingOptions {
+                monthly: true,
+                ..Default::default()
+            },
         )
-        .margin(5)
-        .x_label_area_size(60)
-        .y_label_area_size(80)
-        .build_cartesian_2d(
-            (min_ts - time_margin)..(max_ts + time_margin),
-            0u64..(max_size + size_margin),
+        .unwrap();
+        let tips: Vec<Vec<String>> = samples
+            .iter()
+            .map(|s| {
+                let mut tips = vec![s.commit_hash.clone()];
+                tips.extend(s.extra_tips.iter().cloned());
+                tips
+            })
+            .collect();
+        assert_eq!(
+            tips,
+            vec![
+                vec![c1.to_string()],
+                vec![c1.to_string()],
+                vec![c2.to_string()],
+                vec![c3.to_string(), c2.to_string()],
+            ]
+        );
+
+        // The unmerged branch is included in the final whole-repository measurement
+        let last = samples.last().unwrap();
+        let (whole, _) = measure_size_at_commit(
+            repo.path(),
+            &last.commit_hash,
+            &last.extra_tips,
+            false,
+            true,
         )
-        .map_err(|e| GitSizeError::Plot(e.to_string()))?;
+        .unwrap();
+        let (main_only, _) =
+            measure_size_at_commit(repo.path(), &c3.to_string(), &[], false, true).unwrap();
+        assert!(whole > main_only);
+
+        // With tags selected, annotated tag objects are measured too
+        let tagger =
+            git2::Signature::new("test", "test@example.com", &git2::Time::new(start, 0)).unwrap();
+        repo.tag(
+            "v1.0",
+            &repo.find_object(c3, None).unwrap(),
+            &tagger,
+            "release",
+            false,
+        )
+        .unwrap();
+        let history = History::from_refs(&repo, false, true, true).unwrap();
+        let samples = generate_sample_points(
+            repo.path(),
+            &history,
+            &range,
+            &SamplingOptions {
+                monthly: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let last = samples.last().unwrap();
+        let stats = measure_object_stats(repo.path(), &last.commit_hash, &last.extra_tips).unwrap();
+        assert_eq!(stats.counts.tags, 1);
+        assert!(stats.packed.tags > 0);
+        // Before the tagged commit, the tag is not measured
+        let first = samples.first().unwrap();
+        let stats =
+            measure_object_stats(repo.path(), &first.commit_hash, &first.extra_tips).unwrap();
+        assert_eq!(stats.counts.tags, 0);
 
-    chart
-        .configure_mesh()
-        .light_line_style(TRANSPARENT)
-        .bold_line_style(BLACK.mix(0.3))
-        .x_labels(10)
-        .y_labels(10)
-        .x_label_formatter(&|v| {
-            DateTime::from_timestamp(*v, 0)
-                .map(|dt| dt.format("%Y-%m").to_string())
-                .unwrap_or_default()
-        })
-        .y_label_formatter(&|v| format_size(*v))
-        .draw()
-        .map_err(|e| GitSizeError::Plot(e.to_string()))?;
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
 
-    chart
-        .draw_series(LineSeries::new(
-            plot_data.iter().map(|(t, s)| (*t, *s)),
-            BLUE,
-        ))
-        .map_err(|e| GitSizeError::Plot(e.to_string()))?
-        .label("Cumulative Size")
-        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
+    #[test]
+    fn test_path_prefix() {
+        assert_eq!(path_prefix("README.md", 1), ".");
+        assert_eq!(path_prefix("src/main.rs", 1), "src");
+        assert_eq!(path_prefix("src/bin/tool/main.rs", 1), "src");
+        assert_eq!(path_prefix("src/bin/tool/main.rs", 2), "src/bin");
+        assert_eq!(path_prefix("src/bin/tool/main.rs", 10), "src/bin/tool");
+        assert_eq!(path_prefix("dir with space/file name", 1), "dir with space");
+    }
 
-    chart
-        .configure_series_labels()
-        .background_style(WHITE.mix(0.8))
-        .border_style(BLACK)
-        .draw()
-        .map_err(|e| GitSizeError::Plot(e.to_string()))?;
+    #[test]
+    fn test_measure_breakdown_by_directory() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-breakdown-test-{}", timestamp));
+        std::fs::create_dir_all(temp_dir.join("assets/images")).unwrap();
+        std::fs::create_dir_all(temp_dir.join("src")).unwrap();
 
-    root.present()
-        .map_err(|e| GitSizeError::Plot(e.to_string()))?;
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
 
-    Ok(())
-}
+        std::fs::write(temp_dir.join("README.md"), "readme").unwrap();
+        std::fs::write(temp_dir.join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(temp_dir.join("assets/images/logo.png"), vec![7u8; 5000]).unwrap();
+        std::fs::write(temp_dir.join("assets/icon.png"), vec![8u8; 1000]).unwrap();
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree_id = index.write_tree().unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+        let oid = repo
+            .commit(Some("HEAD"), &signature, &signature, "files", &tree, &[])
+            .unwrap();
 
-    // Resolve and validate repo path
-    let repo_path = if args.repo_path.is_absolute() {
-        args.repo_path.clone()
-    } else {
-        std::env::current_dir()?.join(&args.repo_path)
-    };
+        let groups =
+            measure_breakdown(repo.path(), &oid.to_string(), &[], &Grouping::Directory(1)).unwrap();
+        let names: Vec<&str> = groups.keys().map(|k| k.as_str()).collect();
+        assert_eq!(names, vec![".", "assets", "src"]);
+        assert_eq!(groups["."].uncompressed, 6);
+        assert_eq!(groups["assets"].uncompressed, 6000);
+        assert_eq!(groups["src"].uncompressed, 12);
+        assert!(groups.values().all(|g| g.packed > 0));
 
-    if !repo_path.exists() {
-        return Err(GitSizeError::Validation(format!(
-            "Repository path does not exist: {:?}",
-            repo_path
-        )));
+        let groups =
+            measure_breakdown(repo.path(), &oid.to_string(), &[], &Grouping::Directory(2)).unwrap();
+        assert_eq!(groups["assets"].uncompressed, 1000);
+        assert_eq!(groups["assets/images"].uncompressed, 5000);
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.png", "logo.png"));
+        assert!(glob_match("*.png", "assets/images/logo.png"));
+        assert!(!glob_match("*.png", "logo.png.txt"));
+        assert!(glob_match("lib?.so", "build/libz.so"));
+        assert!(glob_match("vendor/**", "vendor/a/b/c.js"));
+        assert!(!glob_match("vendor/**", "src/vendor/a.js"));
+        assert!(glob_match("**/fixtures/*.json", "fixtures/a.json"));
+        assert!(glob_match(
+            "**/fixtures/*.json",
+            "tests/data/fixtures/a.json"
+        ));
+        assert!(!glob_match(
+            "**/fixtures/*.json",
+            "tests/fixtures/deep/a.json"
+        ));
+        assert!(glob_match("/docs/*.md", "docs/index.md"));
+        assert!(!glob_match("docs/*.md", "docs/api/index.md"));
     }
 
-    // Open repository
-    let repo = Repository::open(&repo_path).map_err(|e| {
-        let git_dir = repo_path.join(".git");
-        let is_git_dir = git_dir.exists();
+    #[test]
+    fn test_extension_and_category_grouping() {
+        let grouping = Grouping::Extension;
+        assert_eq!(grouping.group_of("assets/logo.png"), ".png");
+        assert_eq!(grouping.group_of("assets/LOGO.PNG"), ".png");
+        assert_eq!(grouping.group_of("lib/libfoo.so"), ".so");
+        assert_eq!(grouping.group_of("Makefile"), NO_EXTENSION_GROUP);
+        assert_eq!(grouping.group_of("src/.gitignore"), NO_EXTENSION_GROUP);
+        assert_eq!(grouping.group_of("v1.2/README"), NO_EXTENSION_GROUP);
 
-        // Check if it might be a bare repository
-        let config_file = repo_path.join("config");
-        let is_bare = !is_git_dir && config_file.exists();
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let categories_path =
+            std::env::temp_dir().join(format!("git-size-categories-test-{}.csv", timestamp));
+        std::fs::write(
+            &categories_path,
+            "# glob,category\n*.png, images\n*.jpg,images\ntests/**/*.json,fixtures\n",
+        )
+        .unwrap();
+        let grouping = Grouping::Categories(load_categories(&categories_path).unwrap());
+        assert_eq!(grouping.column(), "category");
+        assert_eq!(grouping.group_of("a/b.png"), "images");
+        assert_eq!(grouping.group_of("c.jpg"), "images");
+        assert_eq!(grouping.group_of("tests/data/x.json"), "fixtures");
+        assert_eq!(grouping.group_of("package.json"), UNCATEGORIZED_GROUP);
+
+        std::fs::write(&categories_path, "*.png\n").unwrap();
+        assert!(load_categories(&categories_path).is_err());
+
+        let _ = std::fs::remove_file(&categories_path);
+    }
 
-        let context = if is_bare {
-            format!(
-                "Cannot open repository at {:?}. The path appears to be a bare git repository. \
-                git-size-history requires a non-bare repository with a working directory. \
-                Either clone this repository to a working directory or use a regular git repository path. \
-                Git error: {}",
-                repo_path, e
-            )
-        } else if is_git_dir {
-            format!(
-                "Cannot open repository at {:?}. The .git directory exists but may be corrupted or inaccessible. \
-                Try running 'git fsck' to check repository integrity. \
-                Git error: {}",
-                repo_path, e
-            )
-        } else {
-            format!(
-                "Cannot open repository at {:?}. Path is not a git repository (no .git directory found). \
-                Make sure you're pointing to a valid git repository. \
-                Git error: {}",
-                repo_path, e
-            )
+    #[test]
+    fn test_measure_object_stats() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-types-test-{}", timestamp));
+        std::fs::create_dir_all(temp_dir.join("src")).unwrap();
+
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+
+        std::fs::write(temp_dir.join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(temp_dir.join("README.md"), "readme").unwrap();
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree_id = index.write_tree().unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+        let oid = repo
+            .commit(Some("HEAD"), &signature, &signature, "files", &tree, &[])
+            .unwrap();
+
+        let sizes = measure_object_stats(repo.path(), &oid.to_string(), &[]).unwrap();
+        assert_eq!(sizes.uncompressed.blobs, 18);
+        assert!(sizes.uncompressed.commits > 0);
+        assert!(sizes.uncompressed.trees > 0);
+        assert_eq!(sizes.uncompressed.tags, 0);
+        assert!(sizes.packed.commits > 0 && sizes.packed.trees > 0 && sizes.packed.blobs > 0);
+
+        // The typed split adds up to the total disk usage
+        let (packed, _) =
+            measure_size_at_commit(repo.path(), &oid.to_string(), &[], false, false).unwrap();
+        assert_eq!(sizes.packed.values().iter().sum::<u64>(), packed);
+
+        let columns = CsvColumns {
+            uncompressed: true,
+            object_types: true,
+            counts: true,
+            ..Default::default()
+        };
+        let data = SizeMeasurement {
+            date: "2024-01-01".to_string(),
+            commit_hash: "0123abcd".to_string(),
+            commit_time: 1_704_110_400,
+            label: None,
+            commit_index: None,
+            cumulative_size: packed,
+            uncompressed_size: Some(18),
+            object_stats: Some(sizes),
+            added_size: None,
         };
-        GitSizeError::Validation(context)
-    })?;
+        assert_eq!(columns.header().len(), columns.record(&data).len());
+        assert_eq!(columns.header()[5], "blobs-size");
+        assert_eq!(columns.record(&data)[5], sizes.packed.blobs.to_string());
+
+        // One commit, two trees (root and src/) and two blobs
+        assert_eq!(sizes.counts.values(), [1, 2, 2, 0]);
+        let header = columns.header();
+        let record = columns.record(&data);
+        let count_of = |name: &str| &record[header.iter().position(|h| *h == name).unwrap()];
+        assert_eq!(count_of("object-count"), "5");
+        assert_eq!(count_of("blob-count"), "2");
+        assert_eq!(count_of("tree-count"), "2");
+
+        // Commit columns follow the target date
+        let columns = CsvColumns {
+            commit: true,
+            ..columns
+        };
+        assert_eq!(
+            columns.header()[..4],
+            ["target-date", "commit", "commit-date", "cumulative-size"]
+        );
+        assert_eq!(
+            columns.record(&data)[..3],
+            ["2024-01-01", "0123abcd", "2024-01-01T12:00:00Z"]
+        );
 
-    // Progress bar for analysis phase - use indeterminate spinner during commit reading
-    let analysis_pb = ProgressBar::new_spinner();
-    analysis_pb.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.green} [{elapsed_precise}] {msg}")
-            .map_err(|e| {
-                GitSizeError::Validation(format!("Failed to set progress style: {}", e))
-            })?,
-    );
-    analysis_pb.enable_steady_tick(std::time::Duration::from_millis(100));
-    analysis_pb.set_message("Reading commit history...");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
 
-    // Get commit range
-    let range = get_commit_range(&repo, &repo_path, &analysis_pb)?;
-    let total_commits = range.total_commits;
+    #[test]
+    fn test_find_top_blobs() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-top-blobs-test-{}", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
 
-    let first_ts = range.first_commit.time().seconds();
-    let last_ts = range.last_commit.time().seconds();
-    let first_dt = DateTime::from_timestamp(first_ts, 0).ok_or_else(|| {
-        GitSizeError::Validation(format!("Invalid first commit timestamp: {}", first_ts))
-    })?;
-    let last_dt = DateTime::from_timestamp(last_ts, 0).ok_or_else(|| {
-        GitSizeError::Validation(format!("Invalid last commit timestamp: {}", last_ts))
-    })?;
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
 
-    let duration = last_dt - first_dt;
-    let years = duration.num_days() as f64 / DAYS_PER_YEAR;
+        // Each commit adds one file, built on top of the previous commit
+        let files: [(&str, Vec<u8>); 4] = [
+            ("small.txt", b"small".to_vec()),
+            (
+                "large.bin",
+                (0..50_000u32).map(|i| (i * 7919 % 251) as u8).collect(),
+            ),
+            (
+                "medium.bin",
+                (0..5_000u32).map(|i| (i * 104_729 % 253) as u8).collect(),
+            ),
+            ("tiny.txt", b"tiny".to_vec()),
+        ];
+        let mut commits = Vec::new();
+        for (name, content) in &files {
+            std::fs::write(temp_dir.join(name), content).unwrap();
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new(name)).unwrap();
+            index.write().unwrap();
+            let tree_id = index.write_tree().unwrap();
+            let tree = repo.find_tree(tree_id).unwrap();
 
-    // Determine sampling strategy
-    let use_yearly = args.yearly || (!args.monthly && years > YEARLY_THRESHOLD_YEARS);
-    analysis_pb.set_message(format!(
-        "Found {} commits ({} to {}, {:.1} years) - {} sampling",
-        total_commits,
-        first_dt.format("%Y-%m-%d"),
-        last_dt.format("%Y-%m-%d"),
-        years,
-        if use_yearly { "yearly" } else { "monthly" }
-    ));
+            let head = repo.head().ok();
+            let parent = head.as_ref().and_then(|h| h.peel_to_commit().ok());
+            let parents: Vec<&git2::Commit> = parent.iter().collect();
 
-    // Generate sample points
-    let samples = generate_sample_points(&repo_path, &range, args.monthly, args.yearly)?;
-    analysis_pb.set_message(format!("Generated {} sample points", samples.len()));
-    analysis_pb.finish_with_message("Analysis complete");
+            let oid = repo
+                .commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    &format!("add {}", name),
+                    &tree,
+                    parents.as_slice(),
+                )
+                .unwrap();
+            commits.push(oid);
+        }
 
-    // Progress bar for sampling phase - shows complete commits count
-    let pb = ProgressBar::new(samples.len() as u64);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template(
-                "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})",
+        let sample = |date: &str, oid: &git2::Oid| SamplePoint {
+            date: date.to_string(),
+            commit_hash: oid.to_string(),
+            commit_time: 0,
+            extra_tips: Vec::new(),
+            label: None,
+            commit_index: None,
+        };
+        let from = sample("2024-01-01", &commits[0]);
+        let to = sample("2024-02-01", &commits[3]);
+
+        let blobs = find_top_blobs(repo.path(), &from, &to, 2).unwrap();
+        assert_eq!(blobs.len(), 2);
+        assert_eq!(blobs[0].path, "large.bin");
+        assert_eq!(blobs[0].size, 50_000);
+        assert_eq!(blobs[0].commit, Some(commits[1].to_string()));
+        assert_eq!(blobs[1].path, "medium.bin");
+        assert_eq!(blobs[1].commit, Some(commits[2].to_string()));
+        assert!(blobs.iter().all(|b| b.start_date == "2024-01-01"));
+
+        // Blobs already reachable from the earlier sample are not reported
+        let blobs = find_top_blobs(repo.path(), &to, &to, 2).unwrap();
+        assert!(blobs.is_empty());
+
+        // A blob introduced by a merge (e.g. while resolving conflicts) is attributed to it
+        let last = repo.find_commit(commits[3]).unwrap();
+        let side = repo
+            .commit(
+                None,
+                &signature,
+                &signature,
+                "side",
+                &last.tree().unwrap(),
+                &[&last],
             )
-            .map_err(|e| GitSizeError::Validation(format!("Failed to set progress style: {}", e)))?
-            .progress_chars("=>-"),
-    );
-    pb.enable_steady_tick(std::time::Duration::from_millis(100));
+            .unwrap();
+        let side = repo.find_commit(side).unwrap();
+        let mut builder = repo.treebuilder(Some(&last.tree().unwrap())).unwrap();
+        let blob = repo.blob(&vec![b'm'; 1000]).unwrap();
+        builder.insert("merge.bin", blob, 0o100644).unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let merge = repo
+            .commit(
+                None,
+                &signature,
+                &signature,
+                "merge",
+                &tree,
+                &[&last, &side],
+            )
+            .unwrap();
+        let blobs = find_top_blobs(repo.path(), &to, &sample("2024-03-01", &merge), 1).unwrap();
+        assert_eq!(blobs.len(), 1);
+        assert_eq!(blobs[0].path, "merge.bin");
+        assert_eq!(blobs[0].commit, Some(merge.to_string()));
 
-    // Wrap progress bar in Arc for thread-safe updates
-    // indicatif::ProgressBar is already thread-safe using atomics
-    let pb = std::sync::Arc::new(pb);
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
 
-    // Measure sizes in parallel for better performance
-    // Using rayon to process multiple sample points concurrently
-    let results: Vec<SizeMeasurement> = samples
-        .par_iter()
-        .map(|sample| {
-            let (packed_size, uncompressed_size) = measure_size_at_commit(
-                &repo_path,
-                &sample.commit_hash,
-                args.debug,
-                args.uncompressed,
-            )?;
-
-            // Thread-safe progress bar increment (indicatif uses atomics internally)
-            pb.inc(1);
-
-            Ok(SizeMeasurement {
-                date: sample.date.clone(),
-                cumulative_size: packed_size,
-                uncompressed_size,
-            })
-        })
-        .collect::<Result<Vec<_>>>()?;
+    #[test]
+    fn test_measure_added_size() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-added-test-{}", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+
+        // The second commit rewrites the file, the third restores the first version
+        let mut samples = Vec::new();
+        for (i, content) in ["first version", "second version", "first version"]
+            .iter()
+            .enumerate()
+        {
+            std::fs::write(temp_dir.join("test.txt"), content).unwrap();
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("test.txt")).unwrap();
+            index.write().unwrap();
+            let tree_id = index.write_tree().unwrap();
+            let tree = repo.find_tree(tree_id).unwrap();
+
+            let head = repo.head().ok();
+            let parent = head.as_ref().and_then(|h| h.peel_to_commit().ok());
+            let parents: Vec<&git2::Commit> = parent.iter().collect();
+
+            let oid = repo
+                .commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    &format!("commit {}", i),
+                    &tree,
+                    parents.as_slice(),
+                )
+                .unwrap();
+            samples.push(SamplePoint {
+                date: format!("2024-0{}-01", i + 1),
+                commit_hash: oid.to_string(),
+                commit_time: 0,
+                extra_tips: Vec::new(),
+                label: None,
+                commit_index: None,
+            });
+        }
+
+        // The first sample point counts everything reachable
+        let (packed, _) =
+            measure_size_at_commit(repo.path(), &samples[0].commit_hash, &[], false, false)
+                .unwrap();
+        assert_eq!(
+            measure_added_size(repo.path(), &samples[0], packed, None).unwrap(),
+            packed
+        );
+
+        // Later ones only count new objects: a commit, a tree and the new blob
+        let (packed_after, _) =
+            measure_size_at_commit(repo.path(), &samples[1].commit_hash, &[], false, false)
+                .unwrap();
+        let added = measure_added_size(
+            repo.path(),
+            &samples[1],
+            packed_after,
+            Some((&samples[0], packed)),
+        )
+        .unwrap();
+        assert!(added > 0);
+        assert_eq!(packed + added, packed_after);
+
+        // Restoring old content only adds the new commit object (the tree and blob exist)
+        let (packed_restored, _) =
+            measure_size_at_commit(repo.path(), &samples[2].commit_hash, &[], false, false)
+                .unwrap();
+        let added = measure_added_size(
+            repo.path(),
+            &samples[2],
+            packed_restored,
+            Some((&samples[1], packed_after)),
+        )
+        .unwrap();
+        let output = git_command(repo.path())
+            .args(["cat-file", "--batch-check=%(objectsize:disk)"])
+            .output_with_input(format!("{}\n", samples[2].commit_hash).into_bytes())
+            .unwrap();
+        let commit_size: u64 = String::from_utf8(output.stdout)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        assert_eq!(added, commit_size);
 
-    // Finish progress bar
-    if let Ok(inner_pb) = std::sync::Arc::try_unwrap(pb) {
-        inner_pb.finish_with_message("Sampling complete");
+        let _ = std::fs::remove_dir_all(&temp_dir);
     }
 
-    // Write CSV
-    println!("Writing CSV to {}", args.output.display());
-    let mut wtr = Writer::from_path(&args.output)?;
-    if args.uncompressed {
-        wtr.write_record(["date", "cumulative-size", "uncompressed-size"])?;
-        for data in &results {
-            wtr.write_record([
-                &data.date,
-                &data.cumulative_size.to_string(),
-                &data.uncompressed_size.unwrap_or(0).to_string(),
-            ])?;
-        }
-    } else {
-        wtr.write_record(["date", "cumulative-size"])?;
-        for data in &results {
-            wtr.write_record([&data.date, &data.cumulative_size.to_string()])?;
+    #[test]
+    fn test_since_until_range() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-range-test-{}", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+
+        // One commit on the 1st of each month of 2020
+        let mut parent: Option<git2::Oid> = None;
+        let mut commits = Vec::new();
+        for month in 1..=12 {
+            let time = NaiveDate::from_ymd_opt(2020, month, 1)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap()
+                .and_utc()
+                .timestamp();
+            let signature =
+                git2::Signature::new("test", "test@example.com", &git2::Time::new(time, 0))
+                    .unwrap();
+            let blob = repo.blob(format!("month {}", month).as_bytes()).unwrap();
+            let mut builder = repo.treebuilder(None).unwrap();
+            builder.insert("data.txt", blob, 0o100644).unwrap();
+            let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+            let parents: Vec<git2::Commit> = parent
+                .iter()
+                .map(|p| repo.find_commit(*p).unwrap())
+                .collect();
+            let parents: Vec<&git2::Commit> = parents.iter().collect();
+            let oid = repo
+                .commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    "commit",
+                    &tree,
+                    &parents,
+                )
+                .unwrap();
+            commits.push(oid.to_string());
+            parent = Some(oid);
         }
-    }
-    wtr.flush()?;
 
-    // Generate plot
-    if let Some(plot_path) = &args.plot {
-        println!("Generating plot: {}", plot_path.display());
-        generate_plot(&results, plot_path)?;
-        println!("Plot saved to {}", plot_path.display());
-    }
+        assert_eq!(parse_date("2020-04-15").unwrap(), 1_586_908_800);
+        assert_eq!(parse_date("2020-04-15T12:00:00Z").unwrap(), 1_586_952_000);
+        // Relative dates count back from now
+        let now = Utc::now().timestamp();
+        let two_years_ago = parse_date("2.years.ago").unwrap();
+        assert!((now - two_years_ago - 2 * 365 * 86_400).abs() < 3 * 86_400);
+        let now = DateTime::parse_from_rfc3339("2024-03-31T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let ago = |value| relative_date(value, now).map(|date| date.to_rfc3339());
+        assert_eq!(ago("1.month.ago").unwrap(), "2024-02-29T12:00:00+00:00");
+        assert_eq!(ago("3 weeks ago").unwrap(), "2024-03-10T12:00:00+00:00");
+        assert_eq!(ago("36.hours.ago").unwrap(), "2024-03-30T00:00:00+00:00");
+        // Typos and unsupported forms are errors instead of "now"
+        for invalid in [
+            "garbage",
+            "2.yaers.ago",
+            "2.years",
+            "yesterday",
+            "2020-13-01",
+            "",
+        ] {
+            assert!(parse_date(invalid).is_err(), "{:?}", invalid);
+        }
 
-    // Print summary
-    println!("\n=== Summary ===");
-    println!("Repository: {}", repo_path.display());
-    println!("Total commits analyzed: {}", range.total_commits);
-    println!(
-        "Time span: {} to {} ({:.1} years)",
-        first_dt.format("%Y-%m-%d"),
-        last_dt.format("%Y-%m-%d"),
-        years
-    );
-    println!("Sample points: {}", results.len());
-    println!(
-        "Sampling method: {}",
-        if use_yearly { "yearly" } else { "monthly" }
-    );
+        let pb = ProgressBar::hidden();
+        let history = History::from_rev(&repo, "HEAD").unwrap();
+        let range = get_commit_range(&repo, &history, &pb).unwrap();
+        let options = SamplingOptions {
+            monthly: true,
+            since: Some(parse_date("2020-04-15").unwrap()),
+            until: Some(parse_date("2020-08-15").unwrap()),
+            ..Default::default()
+        };
+        let samples = generate_sample_points(repo.path(), &history, &range, &options).unwrap();
+
+        // Sample points stay within the range, and measure the state as of each date
+        assert_eq!(samples.first().unwrap().date, "2020-04-15");
+        assert_eq!(samples.last().unwrap().date, "2020-08-15");
+        assert!(samples.len() >= 5);
+        assert_eq!(samples.first().unwrap().commit_hash, commits[3]);
+        assert_eq!(samples.last().unwrap().commit_hash, commits[7]);
+
+        // A range without any commit is rejected
+        let options = SamplingOptions {
+            since: Some(parse_date("2021-01-01").unwrap()),
+            ..Default::default()
+        };
+        assert!(generate_sample_points(repo.path(), &history, &range, &options).is_err());
 
-    if let Some(first) = results.first() {
-        println!(
-            "Initial size ({}): {}",
-            first.date,
-            format_size(first.cumulative_size)
-        );
-    }
-    if let Some(last) = results.last() {
-        println!(
-            "Final size ({}): {}",
-            last.date,
-            format_size(last.cumulative_size)
-        );
+        let _ = std::fs::remove_dir_all(&temp_dir);
     }
 
-    if results.len() >= 2 {
-        if let (Some(first), Some(last)) = (results.first(), results.last()) {
-            let growth = last.cumulative_size.saturating_sub(first.cumulative_size);
-            println!("Total growth: {}", format_size(growth));
+    #[test]
+    fn test_sampling_intervals() {
+        assert_eq!("1d".parse::<Interval>(), Ok(Interval::Days(1)));
+        assert_eq!("90d".parse::<Interval>(), Ok(Interval::Days(90)));
+        assert_eq!("2w".parse::<Interval>(), Ok(Interval::Weeks(2)));
+        assert_eq!("3mo".parse::<Interval>(), Ok(Interval::Months(3)));
+        assert_eq!("1q".parse::<Interval>(), Ok(Interval::Months(3)));
+        assert_eq!("1y".parse::<Interval>(), Ok(Interval::Years(1)));
+        for invalid in ["", "d", "0d", "1", "1m", "-1d", "1.5y"] {
+            assert!(invalid.parse::<Interval>().is_err(), "{:?}", invalid);
         }
-    }
 
-    if args.uncompressed {
-        if let Some(last) = results.last() {
-            if let Some(uncompressed) = last.uncompressed_size {
-                println!("Final uncompressed size: {}", format_size(uncompressed));
-            }
-        }
-    }
+        let date = |y, m, d| {
+            NaiveDate::from_ymd_opt(y, m, d)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+                .and_utc()
+        };
+        let dates = |times: Vec<DateTime<Utc>>| -> Vec<String> {
+            times
+                .iter()
+                .map(|t| t.format("%Y-%m-%d").to_string())
+                .collect()
+        };
 
-    println!("\nOutput written to {}", args.output.display());
-    if let Some(plot_path) = &args.plot {
-        println!("Plot saved to {}", plot_path.display());
-    }
+        // Calendar months do not drift after a short month
+        let options = SamplingOptions {
+            interval: Some(Interval::Months(1)),
+            ..Default::default()
+        };
+        assert_eq!(
+            dates(options.target_times(date(2021, 1, 31), date(2021, 4, 15))),
+            ["2021-01-31", "2021-02-28", "2021-03-31", "2021-04-15"]
+        );
 
-    Ok(())
-}
+        let options = SamplingOptions {
+            interval: Some(Interval::Weeks(1)),
+            ..Default::default()
+        };
+        assert_eq!(
+            dates(options.target_times(date(2021, 1, 1), date(2021, 1, 15))),
+            ["2021-01-01", "2021-01-08", "2021-01-15"]
+        );
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        // Evenly spaced samples always include both ends of the span
+        let options = SamplingOptions {
+            samples: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(
+            dates(options.target_times(date(2020, 1, 1), date(2020, 1, 9))),
+            [
+                "2020-01-01",
+                "2020-01-03",
+                "2020-01-05",
+                "2020-01-07",
+                "2020-01-09"
+            ]
+        );
+        let options = SamplingOptions {
+            samples: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            dates(options.target_times(date(2020, 1, 1), date(2020, 1, 9))),
+            ["2020-01-09"]
+        );
 
-    #[test]
-    fn test_format_size_bytes() {
-        assert_eq!(format_size(0), "0 B");
-        assert_eq!(format_size(1), "1 B");
-        assert_eq!(format_size(999), "999 B");
+        // The age-based heuristic stays the default
+        let options = SamplingOptions::default();
+        assert_eq!(options.interval(3.0), MONTHLY_INTERVAL);
+        assert_eq!(options.interval(10.0), YEARLY_INTERVAL);
+        assert_eq!(options.describe(10.0), "yearly");
     }
 
     #[test]
-    fn test_format_size_kilobytes() {
-        assert_eq!(format_size(1_000), "1.00 KB");
-        assert_eq!(format_size(1_500), "1.50 KB");
-        assert_eq!(format_size(999_999), "1000.00 KB");
-    }
+    fn test_calendar_alignment() {
+        let time = |y, m, d, h| {
+            NaiveDate::from_ymd_opt(y, m, d)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap()
+                .and_utc()
+        };
+        let align = |interval: &str, t| {
+            interval
+                .parse::<Interval>()
+                .unwrap()
+                .align(t)
+                .unwrap()
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        };
 
-    #[test]
-    fn test_format_size_megabytes() {
-        assert_eq!(format_size(1_000_000), "1.00 MB");
-        assert_eq!(format_size(2_500_000), "2.50 MB");
-        assert_eq!(format_size(999_999_999), "1000.00 MB");
+        // 2021-03-17 is a Wednesday
+        let t = time(2021, 3, 17, 15);
+        assert_eq!(align("1d", t), "2021-03-18 00:00");
+        assert_eq!(align("1w", t), "2021-03-22 00:00");
+        assert_eq!(align("1mo", t), "2021-04-01 00:00");
+        assert_eq!(align("1q", t), "2021-04-01 00:00");
+        assert_eq!(align("6mo", t), "2021-07-01 00:00");
+        assert_eq!(align("1y", t), "2022-01-01 00:00");
+        // Boundaries are kept as-is
+        assert_eq!(align("1mo", time(2021, 3, 1, 0)), "2021-03-01 00:00");
+        assert_eq!(align("1w", time(2021, 3, 22, 0)), "2021-03-22 00:00");
+        assert_eq!(align("1q", time(2021, 10, 1, 0)), "2021-10-01 00:00");
+
+        // The default heuristic uses calendar months and years when aligned
+        let options = SamplingOptions {
+            calendar: true,
+            ..Default::default()
+        };
+        let dates: Vec<String> = options
+            .target_times(time(2021, 1, 20, 10), time(2021, 4, 10, 10))
+            .iter()
+            .map(|t| t.format("%Y-%m-%d").to_string())
+            .collect();
+        // The end of the span is not a boundary, so it is left out
+        assert_eq!(dates, ["2021-02-01", "2021-03-01", "2021-04-01"]);
+        assert!(options
+            .target_times(time(2021, 1, 20, 10), time(2021, 1, 30, 10))
+            .is_empty());
+        assert_eq!(options.describe(1.0), "monthly, calendar-aligned");
+        assert_eq!(options.interval(10.0), Interval::Years(1));
     }
 
     #[test]
-    fn test_format_size_gigabytes() {
-        assert_eq!(format_size(1_000_000_000), "1.00 GB");
-        assert_eq!(format_size(5_500_000_000), "5.50 GB");
+    fn test_compare_versions() {
+        let mut tags = vec![
+            "v1.10.0",
+            "release-notes",
+            "v1.9.2",
+            "v1.10.0-rc2",
+            "1.2",
+            "v1.10.0-rc1",
+            "v2.0.0+build5",
+        ];
+        tags.sort_by(|a, b| compare_versions(a, b));
+        assert_eq!(
+            tags,
+            [
+                "1.2",
+                "v1.9.2",
+                "v1.10.0-rc1",
+                "v1.10.0-rc2",
+                "v1.10.0",
+                "v2.0.0+build5",
+                "release-notes"
+            ]
+        );
     }
 
     #[test]
-    fn test_integration_minimal_repo() {
+    fn test_tag_sample_points() {
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_nanos();
-        let temp_dir = std::env::temp_dir().join(format!("git-size-test-{}", timestamp));
+        let temp_dir = std::env::temp_dir().join(format!("git-size-tags-test-{}", timestamp));
         std::fs::create_dir_all(&temp_dir).unwrap();
 
-        // Initialize repo
         let repo = git2::Repository::init(&temp_dir).unwrap();
-        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let day = 86_400;
+        let start = 1_600_000_000;
+        let signature_at = |time: i64| {
+            git2::Signature::new("test", "test@example.com", &git2::Time::new(time, 0)).unwrap()
+        };
 
-        // Create initial commit
-        let tree_id = repo.index().unwrap().write_tree().unwrap();
-        let tree = repo.find_tree(tree_id).unwrap();
-        let oid = repo
-            .commit(
-                Some("HEAD"),
-                &signature,
-                &signature,
-                "initial commit",
-                &tree,
-                &[],
+        // Commit a single file on top of `parents`, dated `time`
+        let commit = |update_ref: Option<&str>, time: i64, parents: &[git2::Oid]| {
+            let signature = signature_at(time);
+            let blob = repo.blob(time.to_string().as_bytes()).unwrap();
+            let mut builder = repo.treebuilder(None).unwrap();
+            builder.insert("data.txt", blob, 0o100644).unwrap();
+            let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+            let parents: Vec<git2::Commit> = parents
+                .iter()
+                .map(|p| repo.find_commit(*p).unwrap())
+                .collect();
+            let parents: Vec<&git2::Commit> = parents.iter().collect();
+            repo.commit(
+                update_ref, &signature, &signature, "commit", &tree, &parents,
             )
+            .unwrap()
+        };
+
+        let c1 = commit(Some("HEAD"), start, &[]);
+        let c2 = commit(Some("HEAD"), start + 10 * day, &[c1]);
+        let c3 = commit(Some("HEAD"), start + 20 * day, &[c2]);
+        // Never merged into HEAD
+        let side = commit(None, start + 30 * day, &[c3]);
+
+        // v1.10.0 is tagged (annotated) later than v1.9.0's commit, but before v1.9.0's tag
+        let tag_object = |oid| repo.find_object(oid, None).unwrap();
+        repo.tag_lightweight("v1.2.0", &tag_object(c1), false)
+            .unwrap();
+        repo.tag(
+            "v1.10.0",
+            &tag_object(c3),
+            &signature_at(start + 21 * day),
+            "release",
+            false,
+        )
+        .unwrap();
+        repo.tag(
+            "v1.9.0",
+            &tag_object(c2),
+            &signature_at(start + 25 * day),
+            "release",
+            false,
+        )
+        .unwrap();
+        repo.tag_lightweight("nightly", &tag_object(c2), false)
+            .unwrap();
+        repo.tag_lightweight("v3.0.0", &tag_object(side), false)
             .unwrap();
 
-        // Test get_commit_range
-        let pb = ProgressBar::hidden();
-        let range = get_commit_range(&repo, &temp_dir, &pb).unwrap();
-        assert_eq!(range.total_commits, 1);
+        let history = History::from_rev(&repo, "HEAD").unwrap();
+        let points = |tags: TagSampling| {
+            let options = SamplingOptions::default();
+            generate_tag_sample_points(&repo, &history, &options, &tags)
+                .unwrap()
+                .into_iter()
+                .map(|s| (s.label.unwrap(), s.commit_hash))
+                .collect::<Vec<_>>()
+        };
 
-        // Test sampling
-        let samples = generate_sample_points(&temp_dir, &range, false, false).unwrap();
-        assert!(!samples.is_empty());
+        // Tags are dated by their tagger (annotated) or commit (lightweight) date
+        let by_date = points(TagSampling {
+            pattern: Some("v*".to_string()),
+            sort: TagSort::Date,
+        });
+        assert_eq!(
+            by_date,
+            [
+                ("v1.2.0".to_string(), c1.to_string()),
+                ("v1.10.0".to_string(), c3.to_string()),
+                ("v1.9.0".to_string(), c2.to_string()),
+            ]
+        );
 
-        // Test size measurement (at least check if it runs without error)
-        let (packed, _) =
-            measure_size_at_commit(&temp_dir, &oid.to_string(), false, false).unwrap();
-        assert!(packed > 0);
+        // Version order only applies to the CSV rows, sample points follow the history
+        let by_version = points(TagSampling {
+            pattern: Some("v*".to_string()),
+            sort: TagSort::Semver,
+        });
+        assert_eq!(by_version, by_date);
+        let rows = TagSort::Semver.csv_rows(&by_version, |(label, _)| Some(label.as_str()));
+        let labels: Vec<&str> = rows.iter().map(|(label, _)| label.as_str()).collect();
+        assert_eq!(labels, ["v1.2.0", "v1.9.0", "v1.10.0"]);
+        assert_eq!(
+            TagSort::Date.csv_rows(&by_date, |(label, _)| Some(label.as_str()))[1].0,
+            "v1.10.0"
+        );
+
+        // Without a pattern, all tags of the history are sampled
+        assert_eq!(points(TagSampling::default()).len(), 4);
+
+        // A pattern matching nothing is an error
+        let tags = TagSampling {
+            pattern: Some("release-*".to_string()),
+            sort: TagSort::Date,
+        };
+        assert!(
+            generate_tag_sample_points(&repo, &history, &SamplingOptions::default(), &tags)
+                .is_err()
+        );
+
+        // With several tips, the tags reachable from any of them are sampled
+        repo.branch("side", &repo.find_commit(side).unwrap(), false)
+            .unwrap();
+        let history = History::from_refs(&repo, false, true, false).unwrap();
+        assert_eq!(history.tips.len(), 2);
+        let tags = generate_tag_sample_points(
+            &repo,
+            &history,
+            &SamplingOptions::default(),
+            &TagSampling::default(),
+        )
+        .unwrap();
+        assert_eq!(tags.len(), 5);
+
+        // Annotated tags are measured with their tag object, lightweight tags without
+        let v1_10 = tags
+            .iter()
+            .find(|s| s.label.as_deref() == Some("v1.10.0"))
+            .unwrap();
+        assert_eq!(v1_10.extra_tips.len(), 1);
+        let stats =
+            measure_object_stats(repo.path(), &v1_10.commit_hash, &v1_10.extra_tips).unwrap();
+        assert_eq!(stats.counts.tags, 1);
+        assert!(stats.packed.tags > 0);
+        let v1_2 = tags
+            .iter()
+            .find(|s| s.label.as_deref() == Some("v1.2.0"))
+            .unwrap();
+        assert!(v1_2.extra_tips.is_empty());
 
-        // Cleanup
         let _ = std::fs::remove_dir_all(&temp_dir);
     }
 
     #[test]
-    fn test_generate_sample_points_yearly_strategy() {
+    fn test_every_n_commits() {
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_nanos();
-        let temp_dir = std::env::temp_dir().join(format!("git-size-yearly-test-{}", timestamp));
+        let temp_dir = std::env::temp_dir().join(format!("git-size-every-n-test-{}", timestamp));
         std::fs::create_dir_all(&temp_dir).unwrap();
 
         let repo = git2::Repository::init(&temp_dir).unwrap();
-        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let day = 86_400;
+        let start = 1_600_000_000;
 
-        // Create commits by appending content to the same file
-        for i in 0..50 {
-            let file_path = temp_dir.join("test.txt");
-            std::fs::write(&file_path, format!("Content {}\n", i)).unwrap();
+        // Ten commits, several of them on the same day
+        let mut parent: Option<git2::Oid> = None;
+        let mut commits = Vec::new();
+        for i in 0..10 {
+            let time = start + (i / 3) * day + i * 60;
+            let signature =
+                git2::Signature::new("test", "test@example.com", &git2::Time::new(time, 0))
+                    .unwrap();
+            let blob = repo.blob(format!("commit {}", i).as_bytes()).unwrap();
+            let mut builder = repo.treebuilder(None).unwrap();
+            builder.insert("data.txt", blob, 0o100644).unwrap();
+            let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+            let parents: Vec<git2::Commit> = parent
+                .iter()
+                .map(|p| repo.find_commit(*p).unwrap())
+                .collect();
+            let parents: Vec<&git2::Commit> = parents.iter().collect();
+            let oid = repo
+                .commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    "commit",
+                    &tree,
+                    &parents,
+                )
+                .unwrap();
+            commits.push(oid.to_string());
+            parent = Some(oid);
+        }
 
-            let mut index = repo.index().unwrap();
-            index.add_path(Path::new("test.txt")).unwrap();
-            index.write().unwrap();
-            let tree_id = index.write_tree().unwrap();
-            let tree = repo.find_tree(tree_id).unwrap();
+        let pb = ProgressBar::hidden();
+        let history = History::from_rev(&repo, "HEAD").unwrap();
+        let range = get_commit_range(&repo, &history, &pb).unwrap();
+        let sample = |options: &SamplingOptions| -> Vec<(u64, String)> {
+            generate_sample_points(repo.path(), &history, &range, options)
+                .unwrap()
+                .into_iter()
+                .map(|s| (s.commit_index.unwrap(), s.commit_hash))
+                .collect()
+        };
 
-            let head = repo.head().ok();
-            let parent = head.as_ref().and_then(|h| h.peel_to_commit().ok());
-            let parents: Vec<&git2::Commit> = parent.iter().collect();
+        // Every third commit, plus the last one; same-day samples are all kept
+        let options = SamplingOptions {
+            every_n_commits: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(
+            sample(&options),
+            [
+                (3, commits[2].clone()),
+                (6, commits[5].clone()),
+                (9, commits[8].clone()),
+                (10, commits[9].clone()),
+            ]
+        );
 
-            repo.commit(
-                Some("HEAD"),
-                &signature,
-                &signature,
-                &format!("commit {}", i),
-                &tree,
-                parents.as_slice(),
-            )
+        // Date bounds skip commits but keep their numbering
+        let options = SamplingOptions {
+            every_n_commits: Some(2),
+            since: Some(start + day),
+            until: Some(start + 2 * day + 8 * 60),
+            ..Default::default()
+        };
+        assert_eq!(
+            sample(&options),
+            [
+                (4, commits[3].clone()),
+                (6, commits[5].clone()),
+                (8, commits[7].clone()),
+                (9, commits[8].clone()),
+            ]
+        );
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_threshold_parsing() {
+        assert_eq!(parse_size("1048576"), Ok(1_048_576));
+        assert_eq!(parse_size("500KB"), Ok(500_000));
+        assert_eq!(parse_size("1.5 GB"), Ok(1_500_000_000));
+        assert_eq!(parse_size("64MiB"), Ok(64 << 20));
+        assert!(parse_size("12 parsecs").is_err());
+        assert!(parse_size("MB").is_err());
+
+        assert_eq!(
+            "50MB".parse::<Threshold>(),
+            Ok(Threshold::Bytes(50_000_000))
+        );
+        assert_eq!("10%".parse::<Threshold>(), Ok(Threshold::Percent(10.0)));
+        assert!("-5%".parse::<Threshold>().is_err());
+
+        assert!(Threshold::Bytes(100).exceeded(1_000_000, 101));
+        assert!(!Threshold::Bytes(100).exceeded(0, 100));
+        assert!(Threshold::Percent(10.0).exceeded(1000, 101));
+        assert!(!Threshold::Percent(10.0).exceeded(1000, 100));
+    }
+
+    #[test]
+    fn test_refine_samples() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-refine-test-{}", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+        let day = 86_400;
+        let start = 1_600_000_000;
+
+        // A small commit every 10 days for a year, and a large one on day 203
+        let mut parent: Option<git2::Oid> = None;
+        let mut days: Vec<i64> = (0..=360).step_by(10).collect();
+        days.push(203);
+        days.sort();
+        for commit_day in days {
+            let time = start + commit_day * day;
+            let signature =
+                git2::Signature::new("test", "test@example.com", &git2::Time::new(time, 0))
+                    .unwrap();
+            let content = if commit_day == 203 {
+                // Incompressible (xorshift) content
+                let mut state = 0x2545_f491_4f6c_dd1du64;
+                (0..200_000)
+                    .map(|_| {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        (state >> 32) as u8
+                    })
+                    .collect()
+            } else {
+                format!("day {}", commit_day).into_bytes()
+            };
+            let blob = repo.blob(&content).unwrap();
+            let mut builder = repo.treebuilder(None).unwrap();
+            builder
+                .insert(format!("day-{}.bin", commit_day), blob, 0o100644)
+                .unwrap();
+            let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+            let parents: Vec<git2::Commit> = parent
+                .iter()
+                .map(|p| repo.find_commit(*p).unwrap())
+                .collect();
+            let parents: Vec<&git2::Commit> = parents.iter().collect();
+            parent = Some(
+                repo.commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    "commit",
+                    &tree,
+                    &parents,
+                )
+                .unwrap(),
+            );
+        }
+
+        let pb = ProgressBar::hidden();
+        let history = History::from_rev(&repo, "HEAD").unwrap();
+        let range = get_commit_range(&repo, &history, &pb).unwrap();
+        let options = SamplingOptions {
+            samples: Some(2),
+            ..Default::default()
+        };
+        let mut samples = generate_sample_points(repo.path(), &history, &range, &options).unwrap();
+        let args = Args::parse_from(["git-size-history", "-o", "unused.csv"]);
+        let git_dir = repo.path().to_path_buf();
+        let measure =
+            |sample: &SamplePoint| measure_sample_point(&git_dir, sample, &args, None, None, None);
+        let mut results: Vec<SizeMeasurement> = samples
+            .iter()
+            .map(|sample| measure(sample).unwrap())
+            .collect();
+
+        let refinement = Refinement {
+            threshold: Threshold::Bytes(100_000),
+            min_interval: Interval::Days(1),
+            max_samples: 100,
+        };
+        let added = refine_samples(
+            repo.path(),
+            &history,
+            &refinement,
+            measure,
+            &mut samples,
+            &mut results,
+            &pb,
+        )
+        .unwrap();
+        assert_eq!(added, samples.len() - 2);
+        assert_eq!(samples.len(), results.len());
+
+        // Bisection narrows the jump down to less than two days, without
+        // spending sample points on the small commits
+        let jump = results
+            .windows(2)
+            .find(|pair| pair[1].cumulative_size - pair[0].cumulative_size > 100_000)
             .unwrap();
+        let jump_start = date_time(&jump[0].date).unwrap().timestamp();
+        let jump_end = date_time(&jump[1].date).unwrap().timestamp();
+        assert!(jump_end - jump_start < 2 * day);
+        assert!(jump_start <= start + 203 * day && start + 203 * day <= jump_end + day);
+        assert!(samples.len() < 15);
+
+        // The budget caps the number of sample points
+        let mut samples = generate_sample_points(repo.path(), &history, &range, &options).unwrap();
+        let mut results: Vec<SizeMeasurement> = samples
+            .iter()
+            .map(|sample| measure(sample).unwrap())
+            .collect();
+        let refinement = Refinement {
+            max_samples: 4,
+            ..refinement
+        };
+        refine_samples(
+            repo.path(),
+            &history,
+            &refinement,
+            measure,
+            &mut samples,
+            &mut results,
+            &pb,
+        )
+        .unwrap();
+        assert_eq!(samples.len(), 4);
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_bisect_size_jump() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-bisect-test-{}", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+
+        // Nine small commits, the sixth one adding a large incompressible blob
+        let mut parent: Option<git2::Oid> = None;
+        let mut commits = Vec::new();
+        for i in 0..9 {
+            let content: Vec<u8> = if i == 5 {
+                let mut state = 0x9e37_79b9_7f4a_7c15u64;
+                (0..200_000)
+                    .map(|_| {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        (state >> 32) as u8
+                    })
+                    .collect()
+            } else {
+                format!("commit {}", i).into_bytes()
+            };
+            let blob = repo.blob(&content).unwrap();
+            let mut builder = repo.treebuilder(None).unwrap();
+            builder
+                .insert(format!("file-{}.bin", i), blob, 0o100644)
+                .unwrap();
+            let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+            let parents: Vec<git2::Commit> = parent
+                .iter()
+                .map(|p| repo.find_commit(*p).unwrap())
+                .collect();
+            let parents: Vec<&git2::Commit> = parents.iter().collect();
+            let oid = repo
+                .commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    &format!("commit {}", i),
+                    &tree,
+                    &parents,
+                )
+                .unwrap();
+            commits.push(oid.to_string());
+            parent = Some(oid);
         }
 
-        let pb = ProgressBar::hidden();
-        let range = get_commit_range(&repo, &temp_dir, &pb).unwrap();
-
-        // Force monthly sampling for this test
-        let samples = generate_sample_points(&temp_dir, &range, true, false).unwrap();
+        let jump = bisect_size_jump(
+            repo.path(),
+            &commits[0],
+            &commits[8],
+            Threshold::Bytes(100_000),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(jump.commit, commits[5]);
+        assert_eq!(jump.parent, commits[4]);
+        assert!(jump.size_after - jump.size_before > 100_000);
+
+        // No commit grows that much
+        assert!(bisect_size_jump(
+            repo.path(),
+            &commits[0],
+            &commits[8],
+            Threshold::Bytes(10_000_000)
+        )
+        .unwrap()
+        .is_none());
+        // The jump is outside of the range
+        assert!(bisect_size_jump(
+            repo.path(),
+            &commits[5],
+            &commits[8],
+            Threshold::Bytes(100_000)
+        )
+        .unwrap()
+        .is_none());
 
-        // Should have at least one sample (the final commit)
-        // Note: Since all commits are created at nearly the same time,
-        // the sampling may only produce one point
-        assert!(!samples.is_empty());
+        // Revisions and dates are both accepted as range ends
+        assert_eq!(
+            resolve_bisect_point(&repo, "HEAD~3", "HEAD").unwrap(),
+            commits[5]
+        );
+        assert_eq!(
+            resolve_bisect_point(&repo, "2100-01-01", "HEAD").unwrap(),
+            commits[8]
+        );
+        // Typos are reported instead of being read as some other point
+        assert!(resolve_bisect_point(&repo, "HAED~3", "HEAD").is_err());
+        assert!(resolve_bisect_point(&repo, "2.yaers.ago", "HEAD").is_err());
+        // A tag named like a date is ambiguous
+        let head = repo.revparse_single("HEAD").unwrap();
+        repo.tag_lightweight("2100-01-01", &head, false).unwrap();
+        assert!(resolve_bisect_point(&repo, "2100-01-01", "HEAD").is_err());
+        assert_eq!(
+            resolve_bisect_point(&repo, "refs/tags/2100-01-01", "HEAD").unwrap(),
+            commits[8]
+        );
 
         let _ = std::fs::remove_dir_all(&temp_dir);
     }
 
     #[test]
-    fn test_generate_sample_points_forced_yearly() {
+    fn test_bisect_command_line() {
+        let args = Args::try_parse_from([
+            "git-size-history",
+            "bisect",
+            "--from",
+            "v1.0",
+            "--threshold",
+            "10MB",
+            "/tmp/repo",
+        ])
+        .unwrap();
+        match args.command {
+            Some(Commands::Bisect(bisect)) => {
+                assert_eq!(bisect.from, "v1.0");
+                assert_eq!(bisect.to, "HEAD");
+                assert_eq!(bisect.threshold, Threshold::Bytes(10_000_000));
+                assert_eq!(bisect.repo_path, PathBuf::from("/tmp/repo"));
+            }
+            None => panic!("bisect subcommand not parsed"),
+        }
+
+        // The output file is still required without a subcommand
+        assert!(Args::try_parse_from(["git-size-history", "/tmp/repo"]).is_err());
+
+        // Options of the analysis do not apply to bisect
+        assert!(Args::try_parse_from([
+            "git-size-history",
+            "--first-parent",
+            "bisect",
+            "--from",
+            "v1.0",
+            "--threshold",
+            "10MB",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_first_parent_history() {
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_nanos();
         let temp_dir =
-            std::env::temp_dir().join(format!("git-size-force-yearly-test-{}", timestamp));
+            std::env::temp_dir().join(format!("git-size-first-parent-test-{}", timestamp));
         std::fs::create_dir_all(&temp_dir).unwrap();
 
         let repo = git2::Repository::init(&temp_dir).unwrap();
-        let signature = git2::Signature::now("test", "test@example.com").unwrap();
-
-        // Create a few commits
-        for i in 0..5 {
-            let file_path = temp_dir.join("test.txt");
-            std::fs::write(&file_path, format!("Content {}\n", i)).unwrap();
-
-            let mut index = repo.index().unwrap();
-            index.add_path(Path::new("test.txt")).unwrap();
-            index.write().unwrap();
-            let tree_id = index.write_tree().unwrap();
-            let tree = repo.find_tree(tree_id).unwrap();
-
-            let head = repo.head().ok();
-            let parent = head.as_ref().and_then(|h| h.peel_to_commit().ok());
-            let parents: Vec<&git2::Commit> = parent.iter().collect();
-
+        let day = 86_400;
+        let start = 1_600_000_000;
+
+        // Commit a single file on top of `parents`, dated `time`
+        let commit = |update_ref: Option<&str>, time: i64, parents: &[git2::Oid]| {
+            let signature =
+                git2::Signature::new("test", "test@example.com", &git2::Time::new(time, 0))
+                    .unwrap();
+            let blob = repo.blob(time.to_string().as_bytes()).unwrap();
+            let mut builder = repo.treebuilder(None).unwrap();
+            builder.insert("data.txt", blob, 0o100644).unwrap();
+            let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+            let parents: Vec<git2::Commit> = parents
+                .iter()
+                .map(|p| repo.find_commit(*p).unwrap())
+                .collect();
+            let parents: Vec<&git2::Commit> = parents.iter().collect();
             repo.commit(
-                Some("HEAD"),
-                &signature,
-                &signature,
-                &format!("commit {}", i),
-                &tree,
-                parents.as_slice(),
+                update_ref, &signature, &signature, "commit", &tree, &parents,
             )
-            .unwrap();
-        }
-
-        let pb = ProgressBar::hidden();
-        let range = get_commit_range(&repo, &temp_dir, &pb).unwrap();
+            .unwrap()
+        };
 
-        // Force yearly sampling
-        let samples = generate_sample_points(&temp_dir, &range, false, true).unwrap();
+        // main: c1 -> c2 -> merge, with a topic commit from day 10 merged on day 100
+        let c1 = commit(Some("HEAD"), start, &[]);
+        let topic = commit(None, start + 10 * day, &[c1]);
+        let c2 = commit(Some("HEAD"), start + 50 * day, &[c1]);
+        let merge = commit(Some("HEAD"), start + 100 * day, &[c2, topic]);
 
-        // Should have at least start and end
-        assert!(!samples.is_empty());
+        let pb = ProgressBar::hidden();
+        let target = vec![DateTime::from_timestamp(start + 20 * day, 0).unwrap()];
+
+        // By default, the state on day 20 is the topic commit
+        let mut history = History::from_rev(&repo, "HEAD").unwrap();
+        let samples = sample_points_at(repo.path(), &history, target.clone()).unwrap();
+        assert_eq!(samples[0].commit_hash, topic.to_string());
+        let range = get_commit_range(&repo, &history, &pb).unwrap();
+        assert_eq!(range.total_commits, 4);
+
+        // Following the mainline, it is the first commit
+        history.first_parent = true;
+        let samples = sample_points_at(repo.path(), &history, target).unwrap();
+        assert_eq!(samples[0].commit_hash, c1.to_string());
+        let range = get_commit_range(&repo, &history, &pb).unwrap();
+        assert_eq!(range.total_commits, 3);
+        assert_eq!(range.last_commit.id(), merge);
+
+        let options = SamplingOptions {
+            every_n_commits: Some(1),
+            ..Default::default()
+        };
+        let samples = generate_sample_points(repo.path(), &history, &range, &options).unwrap();
+        let hashes: Vec<String> = samples.into_iter().map(|s| s.commit_hash).collect();
+        assert_eq!(hashes, [c1.to_string(), c2.to_string(), merge.to_string()]);
 
         let _ = std::fs::remove_dir_all(&temp_dir);
     }
 
     #[test]
-    fn test_generate_sample_points_forced_monthly() {
+    fn test_date_source() {
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_nanos();
         let temp_dir =
-            std::env::temp_dir().join(format!("git-size-force-monthly-test-{}", timestamp));
+            std::env::temp_dir().join(format!("git-size-date-source-test-{}", timestamp));
         std::fs::create_dir_all(&temp_dir).unwrap();
 
         let repo = git2::Repository::init(&temp_dir).unwrap();
-        let signature = git2::Signature::now("test", "test@example.com").unwrap();
-
-        // Create commits
-        for i in 0..10 {
-            let file_path = temp_dir.join("test.txt");
-            std::fs::write(&file_path, format!("Content {}\n", i)).unwrap();
-
-            let mut index = repo.index().unwrap();
-            index.add_path(Path::new("test.txt")).unwrap();
-            index.write().unwrap();
-            let tree_id = index.write_tree().unwrap();
-            let tree = repo.find_tree(tree_id).unwrap();
-
-            let head = repo.head().ok();
-            let parent = head.as_ref().and_then(|h| h.peel_to_commit().ok());
+        let day = 86_400;
+        let start = 1_600_000_000;
+
+        // Commit a single file on HEAD, authored and committed at different times
+        let commit = |authored: i64, committed: i64| {
+            let signature = |time| {
+                git2::Signature::new("test", "test@example.com", &git2::Time::new(time, 0)).unwrap()
+            };
+            let blob = repo.blob(authored.to_string().as_bytes()).unwrap();
+            let mut builder = repo.treebuilder(None).unwrap();
+            builder.insert("data.txt", blob, 0o100644).unwrap();
+            let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+            let parent = repo.head().ok().map(|h| h.peel_to_commit().unwrap());
             let parents: Vec<&git2::Commit> = parent.iter().collect();
-
             repo.commit(
                 Some("HEAD"),
-                &signature,
-                &signature,
-                &format!("commit {}", i),
+                &signature(authored),
+                &signature(committed),
+                "commit",
                 &tree,
-                parents.as_slice(),
+                &parents,
             )
-            .unwrap();
-        }
-
-        let pb = ProgressBar::hidden();
-        let range = get_commit_range(&repo, &temp_dir, &pb).unwrap();
+            .unwrap()
+        };
 
-        // Force monthly sampling
-        let samples = generate_sample_points(&temp_dir, &range, true, false).unwrap();
+        // c2 and c3 were authored early, then rebased on top of c1 on day 100
+        let c1 = commit(start, start + 90 * day);
+        let c2 = commit(start + 10 * day, start + 100 * day);
+        let c3 = commit(start + 20 * day, start + 100 * day);
 
-        assert!(!samples.is_empty());
+        let pb = ProgressBar::hidden();
+        let target = vec![DateTime::from_timestamp(start + 15 * day, 0).unwrap()];
+
+        // By committer date, nothing existed on day 15 and the range starts on day 90
+        let mut history = History::from_rev(&repo, "HEAD").unwrap();
+        let samples = sample_points_at(repo.path(), &history, target.clone()).unwrap();
+        assert!(samples.is_empty());
+        let range = get_commit_range(&repo, &history, &pb).unwrap();
+        let (first, _) = SamplingOptions::default().span(&history, &range).unwrap();
+        assert_eq!(first.timestamp(), start + 90 * day);
+
+        // By author date, the state on day 15 is c2 and the range starts on day 0
+        history.date_source = DateSource::Author;
+        let samples = sample_points_at(repo.path(), &history, target).unwrap();
+        assert_eq!(samples[0].commit_hash, c2.to_string());
+        let (first, last) = SamplingOptions::default().span(&history, &range).unwrap();
+        assert_eq!(first.timestamp(), start);
+        assert_eq!(last.timestamp(), start + 20 * day);
+
+        // The range restricted with --until follows the date source too
+        let options = SamplingOptions {
+            every_n_commits: Some(1),
+            until: Some(start + 15 * day),
+            ..Default::default()
+        };
+        let samples = generate_sample_points(repo.path(), &history, &range, &options).unwrap();
+        let hashes: Vec<String> = samples.into_iter().map(|s| s.commit_hash).collect();
+        assert_eq!(hashes, [c1.to_string(), c2.to_string()]);
+
+        // Whole-repository mode finds the same tips
+        let mut history = History::from_refs(&repo, true, false, false).unwrap();
+        history.date_source = DateSource::Author;
+        let tips =
+            find_tips_at(repo.path(), &history, &[start + 25 * day, start + 5 * day]).unwrap();
+        assert_eq!(tips[0], [(start + 20 * day, c3.to_string())]);
+        assert_eq!(tips[1], [(start, c1.to_string())]);
 
         let _ = std::fs::remove_dir_all(&temp_dir);
     }
 
     #[test]
-    fn test_csv_output_format() {
+    fn test_clock_skew() {
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_nanos();
-        let temp_dir = std::env::temp_dir().join(format!("git-size-csv-test-{}", timestamp));
+        let temp_dir = std::env::temp_dir().join(format!("git-size-skew-test-{}", timestamp));
         std::fs::create_dir_all(&temp_dir).unwrap();
 
         let repo = git2::Repository::init(&temp_dir).unwrap();
-        let signature = git2::Signature::now("test", "test@example.com").unwrap();
-        let tree_id = repo.index().unwrap().write_tree().unwrap();
-        let tree = repo.find_tree(tree_id).unwrap();
-        let _oid = repo
-            .commit(
-                Some("HEAD"),
-                &signature,
-                &signature,
-                "initial commit",
-                &tree,
-                &[],
+        let day = 86_400;
+        let start = 1_600_000_000;
+
+        // Commit a single file on top of `parents`, dated `time`
+        let commit = |update_ref: Option<&str>, time: i64, parents: &[git2::Oid]| {
+            let signature =
+                git2::Signature::new("test", "test@example.com", &git2::Time::new(time, 0))
+                    .unwrap();
+            let blob = repo.blob(time.to_string().as_bytes()).unwrap();
+            let mut builder = repo.treebuilder(None).unwrap();
+            builder.insert("data.txt", blob, 0o100644).unwrap();
+            let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+            let parents: Vec<git2::Commit> = parents
+                .iter()
+                .map(|p| repo.find_commit(*p).unwrap())
+                .collect();
+            let parents: Vec<&git2::Commit> = parents.iter().collect();
+            repo.commit(
+                update_ref, &signature, &signature, "commit", &tree, &parents,
             )
-            .unwrap();
-
-        let output_path = temp_dir.join("output.csv");
+            .unwrap()
+        };
 
-        // Test CSV writing directly
-        let mut wtr = Writer::from_path(&output_path).unwrap();
-        wtr.write_record(["date", "cumulative-size"]).unwrap();
-        wtr.write_record(["2024-01-01", "1234"]).unwrap();
-        wtr.flush().unwrap();
+        // Mainline with a commit dated in the future and one dated before the root,
+        // and a topic branch whose commits are dated before the commit they follow
+        let c1 = commit(Some("HEAD"), start, &[]);
+        let future = commit(Some("HEAD"), start + 1000 * day, &[c1]);
+        let c3 = commit(Some("HEAD"), start + 20 * day, &[future]);
+        let past = commit(Some("HEAD"), start - 100 * day, &[c3]);
+        let c5 = commit(Some("HEAD"), start + 40 * day, &[past]);
+        let topic1 = commit(None, start + 60 * day, &[c5]);
+        let topic2 = commit(None, start + 30 * day, &[topic1]);
+        let c6 = commit(Some("HEAD"), start + 50 * day, &[c5]);
+        let merge = commit(Some("HEAD"), start + 70 * day, &[c6, topic2]);
+
+        let targets: Vec<DateTime<Utc>> = [5, 25, 35, 45, 55, 65, 2000]
+            .iter()
+            .map(|d| DateTime::from_timestamp(start + d * day, 0).unwrap())
+            .collect();
+
+        // Commits dated after a descendant take the date of that descendant:
+        // everything up to "past" is dated like "past", and "c5" like "topic2".
+        // Walking in timestamp order would sample "c5" after "topic2", going back
+        // in history.
+        let history = History::from_rev(&repo, "HEAD").unwrap();
+        let samples = sample_points_at(repo.path(), &history, targets.clone()).unwrap();
+        let hashes: Vec<String> = samples.iter().map(|s| s.commit_hash.clone()).collect();
+        let expected = [past, past, topic2, topic2, c6, c6, merge].map(|c| c.to_string());
+        assert_eq!(hashes, expected);
+
+        // No sample point is an ancestor of the previous one: history never goes backwards
+        for pair in samples.windows(2) {
+            let older = git2::Oid::from_str(&pair[0].commit_hash).unwrap();
+            let newer = git2::Oid::from_str(&pair[1].commit_hash).unwrap();
+            assert!(!repo.graph_descendant_of(older, newer).unwrap());
+        }
 
-        // Verify CSV content
-        let content = std::fs::read_to_string(&output_path).unwrap();
-        assert!(content.contains("date,cumulative-size"));
-        assert!(content.contains("2024-01-01,1234"));
+        // Whole-repository mode agrees
+        let history = History::from_refs(&repo, true, false, false).unwrap();
+        let samples = sample_points_at(repo.path(), &history, targets).unwrap();
+        let hashes: Vec<String> = samples.iter().map(|s| s.commit_hash.clone()).collect();
+        assert_eq!(hashes, expected);
 
         let _ = std::fs::remove_dir_all(&temp_dir);
     }
 
     #[test]
-    fn test_measure_size_with_uncompressed() {
+    fn test_measurement_cache() {
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_nanos();
-        let temp_dir = std::env::temp_dir().join(format!("git-size-uncomp-test-{}", timestamp));
+        let temp_dir = std::env::temp_dir().join(format!("git-size-cache-test-{}", timestamp));
         std::fs::create_dir_all(&temp_dir).unwrap();
+        let cache_dir = temp_dir.join("cache");
 
-        let repo = git2::Repository::init(&temp_dir).unwrap();
+        let repo = git2::Repository::init(temp_dir.join("repo")).unwrap();
         let signature = git2::Signature::now("test", "test@example.com").unwrap();
-
-        // Create a commit with some content
-        let file_path = temp_dir.join("test.txt");
-        std::fs::write(&file_path, "Hello, World! This is some test content.").unwrap();
-
-        let mut index = repo.index().unwrap();
-        index.add_path(Path::new("test.txt")).unwrap();
-        index.write().unwrap();
-        let tree_id = index.write_tree().unwrap();
-        let tree = repo.find_tree(tree_id).unwrap();
-        let oid = repo
-            .commit(
+        let commit = |content: &str| {
+            let blob = repo.blob(content.as_bytes()).unwrap();
+            let mut builder = repo.treebuilder(None).unwrap();
+            builder.insert("data.txt", blob, 0o100644).unwrap();
+            let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+            let parent = repo.head().ok().map(|h| h.peel_to_commit().unwrap());
+            let parents: Vec<&git2::Commit> = parent.iter().collect();
+            repo.commit(
                 Some("HEAD"),
                 &signature,
                 &signature,
-                "commit with content",
+                "commit",
                 &tree,
-                &[],
+                &parents,
             )
-            .unwrap();
+            .unwrap()
+        };
+        let oid = commit("first version");
+        let sample = SamplePoint {
+            date: "2024-01-01".to_string(),
+            commit_hash: oid.to_string(),
+            commit_time: 0,
+            extra_tips: Vec::new(),
+            label: None,
+            commit_index: None,
+        };
+        let args = Args::parse_from(["git-size-history", "-o", "unused.csv", "-U"]);
+        let repack = |options: &[&str]| {
+            let status = git_command(repo.path())
+                .arg("repack")
+                .args(options)
+                .arg("-q")
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        let cache_files = || std::fs::read_dir(&cache_dir).unwrap().count();
+
+        // Measurements of a loose commit count its loose objects: they are not stored
+        let cache = MeasurementCache::open(&cache_dir, &repo).unwrap();
+        measure_sample_point(repo.path(), &sample, &args, Some(&cache), None, None).unwrap();
+        cache.save(&repo).unwrap();
+        assert_eq!(cache_files(), 0);
+
+        // Once packed, the first run measures and stores the sizes
+        repack(&["-a", "-d"]);
+        let cache = MeasurementCache::open(&cache_dir, &repo).unwrap();
+        let measured =
+            measure_sample_point(repo.path(), &sample, &args, Some(&cache), None, None).unwrap();
+        assert_eq!(cache.hits(), 0);
+        cache.save(&repo).unwrap();
+
+        // The next run reuses them, as long as they include what is asked for
+        let cache = MeasurementCache::open(&cache_dir, &repo).unwrap();
+        let cached = cache.get(&sample, true, false).unwrap();
+        assert_eq!(cached.cumulative_size, measured.cumulative_size);
+        assert_eq!(cached.uncompressed_size, measured.uncompressed_size);
+        assert!(cache.get(&sample, true, true).is_none());
+        let reused =
+            measure_sample_point(repo.path(), &sample, &args, Some(&cache), None, None).unwrap();
+        assert_eq!(reused.cumulative_size, measured.cumulative_size);
+        assert_eq!(cache.hits(), 2);
+
+        // New objects leave earlier measurements valid, loose or packed
+        let second = SamplePoint {
+            date: "2024-02-01".to_string(),
+            commit_hash: commit("second version").to_string(),
+            commit_time: 0,
+            extra_tips: Vec::new(),
+            label: None,
+            commit_index: None,
+        };
+        let cache = MeasurementCache::open(&cache_dir, &repo).unwrap();
+        assert!(cache.get(&sample, false, false).is_some());
+
+        // Loose objects only keep the measurements reaching them out of the cache
+        let stats_args = Args::parse_from(["git-size-history", "-o", "unused.csv", "--counts"]);
+        measure_sample_point(repo.path(), &sample, &stats_args, Some(&cache), None, None).unwrap();
+        measure_sample_point(repo.path(), &second, &args, Some(&cache), None, None).unwrap();
+        cache.save(&repo).unwrap();
+        let cache = MeasurementCache::open(&cache_dir, &repo).unwrap();
+        assert!(cache.get(&sample, false, true).is_some());
+        assert!(cache.get(&second, false, false).is_none());
+
+        repack(&["-d"]);
+        let cache = MeasurementCache::open(&cache_dir, &repo).unwrap();
+        assert!(cache.get(&sample, false, false).is_some());
+        measure_sample_point(repo.path(), &second, &args, Some(&cache), None, None).unwrap();
+        cache.save(&repo).unwrap();
+
+        // All valid measurements are kept in a single state
+        assert_eq!(cache_files(), 2);
+        let cache = MeasurementCache::open(&cache_dir, &repo).unwrap();
+        assert!(cache.get(&sample, true, false).is_some());
+        assert!(cache.get(&second, true, false).is_some());
+
+        // Repacking everything moves the objects: the measurements are dropped
+        repack(&["-a", "-d"]);
+        let cache = MeasurementCache::open(&cache_dir, &repo).unwrap();
+        assert!(cache.get(&sample, false, false).is_none());
+        assert_eq!(cache_files(), 0);
 
-        // Test with uncompressed calculation
-        let (packed, uncompressed) =
-            measure_size_at_commit(&temp_dir, &oid.to_string(), false, true).unwrap();
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
 
-        assert!(packed > 0);
-        assert!(uncompressed.is_some());
-        assert!(uncompressed.unwrap() > 0);
+    #[test]
+    fn test_csv_output_round_trip() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-append-test-{}", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+        let path = temp_dir.join("sizes.csv");
+
+        let columns = CsvColumns {
+            commit: true,
+            label: true,
+            commit_index: false,
+            uncompressed: true,
+            added: true,
+            object_types: true,
+            counts: true,
+        };
+        let totals = |base: u64| TypeTotals {
+            commits: base,
+            trees: base + 1,
+            blobs: base + 2,
+            tags: base + 3,
+        };
+        let measurement = |date: &str, label: Option<&str>| SizeMeasurement {
+            date: date.to_string(),
+            commit_hash: "0123abcd".to_string(),
+            commit_time: 1_704_110_400,
+            label: label.map(str::to_string),
+            commit_index: None,
+            cumulative_size: 1000,
+            uncompressed_size: Some(5000),
+            object_stats: Some(ObjectStats {
+                packed: totals(10),
+                uncompressed: totals(20),
+                counts: totals(30),
+            }),
+            added_size: Some(100),
+        };
+        let data = [
+            measurement("2024-01-01", Some("v1.0")),
+            measurement("2024-02-01", None),
+        ];
+        write_csv_atomically(&path, |wtr| {
+            wtr.write_record(columns.header())?;
+            for row in &data {
+                wtr.write_record(columns.record(row))?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        // Only the output file is left behind
+        assert_eq!(std::fs::read_dir(&temp_dir).unwrap().count(), 1);
+
+        // Existing rows are read back as written
+        let (records, measurements) = read_csv_output(&path, &columns).unwrap();
+        assert_eq!(records.len(), 2);
+        for (row, parsed) in data.iter().zip(&measurements) {
+            assert_eq!(columns.record(parsed), columns.record(row));
+            assert_eq!(parsed.label, row.label);
+            assert_eq!(parsed.commit_time, row.commit_time);
+        }
+        assert_eq!(
+            records[1].iter().collect::<Vec<_>>(),
+            columns.record(&data[1])
+        );
+
+        // A file written with other options is rejected
+        let other = CsvColumns {
+            counts: false,
+            ..columns
+        };
+        assert!(read_csv_output(&path, &other).is_err());
 
         let _ = std::fs::remove_dir_all(&temp_dir);
     }
 
     #[test]
-    fn test_error_handling_invalid_path() {
-        let invalid_path = PathBuf::from("/nonexistent/path/to/repo");
-        let result = Repository::open(&invalid_path);
-        assert!(result.is_err());
+    fn test_samples_to_append() {
+        let sample = |date: &str, label: Option<&str>, commit_index: Option<u64>| SamplePoint {
+            date: date.to_string(),
+            commit_hash: "0123abcd".to_string(),
+            commit_time: 0,
+            extra_tips: Vec::new(),
+            label: label.map(str::to_string),
+            commit_index,
+        };
+        let row = |date: &str, label: Option<&str>, commit_index: Option<u64>| SizeMeasurement {
+            date: date.to_string(),
+            commit_hash: "0123abcd".to_string(),
+            commit_time: 0,
+            label: label.map(str::to_string),
+            commit_index,
+            cumulative_size: 1000,
+            uncompressed_size: None,
+            object_stats: None,
+            added_size: None,
+        };
+        let dates = |samples: Vec<SamplePoint>| -> Vec<String> {
+            samples.into_iter().map(|sample| sample.date).collect()
+        };
+
+        // The end of the earlier history (2024-03-10) is replaced by this run's points
+        let mut existing = vec![
+            row("2024-01-01", None, None),
+            row("2024-02-01", None, None),
+            row("2024-03-10", None, None),
+        ];
+        let samples = [
+            "2024-01-01",
+            "2024-02-01",
+            "2024-03-01",
+            "2024-04-01",
+            "2024-04-20",
+        ]
+        .map(|date| sample(date, None, None));
+        assert_eq!(
+            dates(samples_to_append(samples.into(), &mut existing)),
+            ["2024-03-01", "2024-04-01", "2024-04-20"]
+        );
+        assert_eq!(existing.len(), 2);
+
+        // A last row on this run's dates is kept
+        let samples = ["2024-02-01", "2024-03-01"].map(|date| sample(date, None, None));
+        assert_eq!(
+            dates(samples_to_append(samples.into(), &mut existing)),
+            ["2024-03-01"]
+        );
+        assert_eq!(existing.len(), 2);
+
+        // Tags are new when no row has them, whatever their date or version
+        let mut existing = vec![
+            row("2024-01-01", Some("v1.0"), None),
+            row("2024-03-01", Some("v2.0"), None),
+        ];
+        let samples = vec![
+            sample("2024-01-01", Some("v1.0"), None),
+            sample("2024-03-01", Some("v1.1"), None),
+            sample("2024-02-01", Some("v1.0.1"), None),
+            sample("2024-03-01", Some("v2.0"), None),
+        ];
+        let labels: Vec<String> = samples_to_append(samples, &mut existing)
+            .into_iter()
+            .filter_map(|sample| sample.label)
+            .collect();
+        assert_eq!(labels, ["v1.1", "v1.0.1"]);
+        assert_eq!(existing.len(), 2);
+
+        // With --added, a new tag follows the latest point dated before it
+        let samples = [
+            sample("2024-02-01", Some("v1.0.1"), None),
+            sample("2024-04-01", Some("v1.1"), None),
+            sample("2024-05-01", Some("v1.2"), None),
+        ];
+        let preceding: Vec<Option<&str>> = preceding_rows(&existing, &samples)
+            .into_iter()
+            .map(|row| row.and_then(|row| row.label.as_deref()))
+            .collect();
+        assert_eq!(preceding, [Some("v1.0"), Some("v2.0"), None]);
+        let dated = [row("2024-01-01", None, None), row("2024-02-01", None, None)];
+        let samples = ["2024-03-01", "2024-04-01"].map(|date| sample(date, None, None));
+        let preceding: Vec<Option<&str>> = preceding_rows(&dated, &samples)
+            .into_iter()
+            .map(|row| row.map(|row| row.date.as_str()))
+            .collect();
+        assert_eq!(preceding, [Some("2024-02-01"), None]);
+
+        // Commit sample points follow the last commit index
+        let mut existing = vec![
+            row("2024-01-01", None, Some(1)),
+            row("2024-01-01", None, Some(2)),
+        ];
+        let samples = (1..=4).map(|index| sample("2024-01-01", None, Some(index)));
+        let indices: Vec<u64> = samples_to_append(samples.collect(), &mut existing)
+            .into_iter()
+            .filter_map(|sample| sample.commit_index)
+            .collect();
+        assert_eq!(indices, [3, 4]);
     }
 
     #[test]
-    fn test_error_handling_empty_repo() {
+    fn test_checkpoint_journal() {
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_nanos();
-        let temp_dir = std::env::temp_dir().join(format!("git-size-empty-test-{}", timestamp));
+        let temp_dir = std::env::temp_dir().join(format!("git-size-journal-test-{}", timestamp));
         std::fs::create_dir_all(&temp_dir).unwrap();
+        let output = temp_dir.join("sizes.csv");
+        let journal_path = temp_dir.join("sizes.csv.journal");
+
+        let sample = |date: &str| SamplePoint {
+            date: date.to_string(),
+            commit_hash: "0123abcd".to_string(),
+            commit_time: 0,
+            extra_tips: vec!["4567cdef".to_string()],
+            label: None,
+            commit_index: None,
+        };
+        let sizes = |size: u64| CachedSizes {
+            cumulative_size: size,
+            uncompressed_size: Some(size * 4),
+            object_stats: None,
+        };
 
-        // Initialize repo but don't create any commits
-        let repo = git2::Repository::init(&temp_dir).unwrap();
-
-        // get_commit_range should return an error for empty repo
-        let pb = ProgressBar::hidden();
-        let result = get_commit_range(&repo, &temp_dir, &pb);
-        assert!(result.is_err());
+        let journal = Journal::open(&output, false).unwrap();
+        journal.record(&sample("2024-01-01"), sizes(100)).unwrap();
+        journal.record(&sample("2024-02-01"), sizes(200)).unwrap();
+        drop(journal);
+
+        // A run killed in the middle of a write leaves a partial line
+        let mut content = std::fs::read_to_string(&journal_path).unwrap();
+        content.push_str("2024-03-01 0123abcd+4567cdef,3");
+        std::fs::write(&journal_path, content).unwrap();
+
+        // Resuming keeps the complete measurements only, and appends after them
+        let journal = Journal::open(&output, true).unwrap();
+        assert_eq!(
+            journal.get(&sample("2024-02-01"), true, false),
+            Some(sizes(200))
+        );
+        assert_eq!(journal.get(&sample("2024-03-01"), false, false), None);
+        assert_eq!(journal.get(&sample("2024-01-01"), false, true), None);
+        assert_eq!(journal.hits(), 1);
+        journal.record(&sample("2024-03-01"), sizes(300)).unwrap();
+        // Added sizes are journaled along with the previous sample point
+        let (jan, feb, mar) = (
+            sample("2024-01-01"),
+            sample("2024-02-01"),
+            sample("2024-03-01"),
+        );
+        journal.record_added(&jan, None, 100).unwrap();
+        journal.record_added(&feb, Some(&jan), 20).unwrap();
+        drop(journal);
+
+        let journal = Journal::open(&output, true).unwrap();
+        assert_eq!(
+            journal.get(&sample("2024-03-01"), true, false),
+            Some(sizes(300))
+        );
+        assert!(journal.get(&sample("2024-01-01"), false, false).is_some());
+        assert_eq!(journal.get_added(&jan, None), Some(100));
+        assert_eq!(journal.get_added(&feb, Some(&jan)), Some(20));
+        // Refinement may have put another sample point before it
+        assert_eq!(journal.get_added(&mar, Some(&jan)), None);
+        assert_eq!(journal.get_added(&feb, None), None);
+        drop(journal);
+
+        // Without --resume, the journal of an interrupted or killed run is not discarded
+        assert!(Journal::open(&output, false).is_err());
+        assert!(journal_path.exists());
+
+        // That of a run which failed with an error is
+        let journal = Journal::open(&output, true).unwrap();
+        journal.record(&sample("2024-04-01"), sizes(400)).unwrap();
+        journal.fail(GitSizeError::Command("git failed".to_string()));
+        drop(journal);
+        let journal = Journal::open(&output, false).unwrap();
+        assert!(!journal_path.exists());
+        assert_eq!(journal.get(&sample("2024-02-01"), false, false), None);
+
+        // The journal is only created by the first measurement, and removed once done
+        journal.remove().unwrap();
+        std::fs::write(&journal_path, "").unwrap();
+        let journal = Journal::open(&output, false).unwrap();
+        assert!(!journal_path.exists());
+        journal.record(&sample("2024-01-01"), sizes(100)).unwrap();
+        assert!(journal_path.exists());
+        journal.remove().unwrap();
+        assert!(!journal_path.exists());
 
         let _ = std::fs::remove_dir_all(&temp_dir);
     }
 
     #[test]
-    fn test_multi_commit_repository() {
+    fn test_tracked_children() {
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_nanos();
-        let temp_dir = std::env::temp_dir().join(format!("git-size-multi-test-{}", timestamp));
+        let temp_dir = std::env::temp_dir().join(format!("git-size-tracked-test-{}", timestamp));
         std::fs::create_dir_all(&temp_dir).unwrap();
-
         let repo = git2::Repository::init(&temp_dir).unwrap();
-        let signature = git2::Signature::now("test", "test@example.com").unwrap();
-
-        // Create multiple commits by modifying the same file
-        let mut commits = Vec::new();
-        for i in 0..5 {
-            let file_path = temp_dir.join("test.txt");
-            std::fs::write(&file_path, format!("Content of file {}\n", i)).unwrap();
-
-            let mut index = repo.index().unwrap();
-            index.add_path(Path::new("test.txt")).unwrap();
-            index.write().unwrap();
-            let tree_id = index.write_tree().unwrap();
-            let tree = repo.find_tree(tree_id).unwrap();
-
-            let head = repo.head().ok();
-            let parent = head.as_ref().and_then(|h| h.peel_to_commit().ok());
-            let parents: Vec<&git2::Commit> = parent.iter().collect();
+        let running = |pid: u32| RUNNING_CHILDREN.lock().unwrap().contains(&pid);
 
-            let oid = repo
-                .commit(
-                    Some("HEAD"),
-                    &signature,
-                    &signature,
-                    &format!("commit {}", i),
-                    &tree,
-                    parents.as_slice(),
-                )
+        // Registered while running, until waited for
+        let output = git_command(repo.path())
+            .args(["rev-parse", "--is-bare-repository"])
+            .output_tracked()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "false");
+
+        let mut child = git_command(repo.path())
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn_tracked()
+            .unwrap();
+        let pid = child.id();
+        assert!(running(pid));
+        drop(child.stdin.take());
+        assert!(child.wait().unwrap().success());
+        assert!(!running(pid));
+
+        // Children dropped while running are killed and reaped
+        let child = git_command(repo.path())
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn_tracked()
+            .unwrap();
+        let pid = child.id();
+        assert!(running(pid));
+        drop(child);
+        assert!(!running(pid));
+
+        // An exited child stays registered and unreaped until `wait` untracks it
+        #[cfg(unix)]
+        {
+            let mut child = git_command(repo.path())
+                .args(["rev-parse", "--git-dir"])
+                .stdout(Stdio::null())
+                .spawn_tracked()
                 .unwrap();
-            commits.push(oid);
+            let pid = child.id();
+            wait_exited(&child).unwrap();
+            assert!(running(pid));
+            // SAFETY: signal 0 only checks that the process (a zombie here) exists
+            assert_eq!(unsafe { libc::kill(pid as libc::pid_t, 0) }, 0);
+            assert!(child.wait().unwrap().success());
+            assert!(!running(pid));
         }
 
-        // Test get_commit_range
-        let pb = ProgressBar::hidden();
-        let range = get_commit_range(&repo, &temp_dir, &pb).unwrap();
-        assert_eq!(range.total_commits, 5);
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
 
-        // Test sampling
-        let samples = generate_sample_points(&temp_dir, &range, false, false).unwrap();
-        assert!(!samples.is_empty());
+    #[test]
+    fn test_memory_budget() {
+        // 100k objects walked take about 16 MB
+        assert_eq!(MemoryBudget::new(50_000_000, 100_000, false).slots, 3);
+        assert_eq!(MemoryBudget::new(10_000_000, 100_000, false).slots, 1);
+        assert_eq!(MemoryBudget::new(200 << 20, 100_000, true).slots, 1);
+        assert_eq!(MemoryBudget::new(400 << 20, 1_000, true).slots, 4);
+
+        // Measurements beyond the budget wait for a slot
+        let budget = MemoryBudget::new(2 * WALK_MEMORY_PER_OBJECT, 1, false);
+        assert_eq!(budget.slots, 2);
+        let running = AtomicUsize::new(0);
+        let most_running = AtomicUsize::new(0);
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(6)
+            .build()
+            .unwrap()
+            .install(|| {
+                (0..24).into_par_iter().for_each(|_| {
+                    let _permit = budget.acquire();
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    most_running.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(std::time::Duration::from_millis(2));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                })
+            });
+        assert!(most_running.load(Ordering::SeqCst) <= 2);
+        assert_eq!(*budget.available.lock().unwrap(), 2);
 
-        // Test size measurement at different commits
-        for (i, commit_oid) in commits.iter().enumerate() {
-            let (packed, _) =
-                measure_size_at_commit(&temp_dir, &commit_oid.to_string(), false, false).unwrap();
-            assert!(packed > 0, "Size measurement failed for commit {}", i);
-        }
+        // Loose and packed objects are counted
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("git-size-budget-test-{}", timestamp));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+        let repo = git2::Repository::init(&temp_dir).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let tree_id = repo.index().unwrap().write_tree().unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+        let parent = repo
+            .commit(Some("HEAD"), &signature, &signature, "first", &tree, &[])
+            .unwrap();
+        assert_eq!(count_objects(repo.path()).unwrap(), 2);
+        let status = git_command(repo.path())
+            .args(["repack", "-a", "-d", "-q"])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let parent = repo.find_commit(parent).unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "second",
+            &tree,
+            &[&parent],
+        )
+        .unwrap();
+        assert_eq!(count_objects(repo.path()).unwrap(), 3);
 
         let _ = std::fs::remove_dir_all(&temp_dir);
     }